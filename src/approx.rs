@@ -0,0 +1,192 @@
+use std::error;
+use std::fmt;
+
+use ::{sorted_keys, Map, Path, PathSegment, Value};
+
+/// Tolerance applied to `F64` values and the elements of `LSTF32`/`LSTF64` lists.
+///
+/// NaN always equals NaN, and infinities only equal themselves.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tolerance {
+    /// `|a - b| <= tolerance`
+    Absolute(f64),
+    /// `|a - b| <= tolerance * max(|a|, |b|)`
+    Relative(f64),
+    /// At most this number of representable floats between `a` and `b`.
+    Ulps(u64),
+}
+
+/// First difference found by `Value::approx_eq`, `None` standing for a missing map key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApproxMismatch {
+    pub path: Path,
+    pub left: Option<Value>,
+    pub right: Option<Value>,
+}
+
+impl fmt::Display for ApproxMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = self.path.to_display_string();
+        write!(f, "{} differs -- ", path)?;
+        write_side(f, &self.left)?;
+        write!(f, " != ")?;
+        write_side(f, &self.right)
+    }
+}
+
+fn write_side(f: &mut fmt::Formatter, value: &Option<Value>) -> fmt::Result {
+    match *value {
+        Some(ref value) => write!(f, "{}", value),
+        None => write!(f, "missing"),
+    }
+}
+
+impl error::Error for ApproxMismatch {}
+
+impl Value {
+    /// Compares two values exactly, except for floats which are compared with `tolerance`.
+    pub fn approx_eq(&self, other: &Value, tolerance: Tolerance) -> Result<(), Box<ApproxMismatch>> {
+        let mut path = Path::new();
+        match approx_eq(self, other, tolerance, &mut path) {
+            None => Ok(()),
+            Some((left, right)) => Err(Box::new(ApproxMismatch { path, left, right })),
+        }
+    }
+}
+
+type Mismatch = Option<(Option<Value>, Option<Value>)>;
+
+fn mismatch(left: &Value, right: &Value) -> Mismatch {
+    Some((Some(left.clone()), Some(right.clone())))
+}
+
+// On mismatch `path` is left pointing to the differing value.
+fn approx_eq(left: &Value, right: &Value, tolerance: Tolerance, path: &mut Path) -> Mismatch {
+    match (left, right) {
+        (Value::F64(a), Value::F64(b)) => {
+            if close_f64(*a, *b, tolerance) { None } else { mismatch(left, right) }
+        }
+        (Value::LSTF64(a), Value::LSTF64(b)) => {
+            approx_eq_floats(a, b, |a, b| close_f64(*a, *b, tolerance), |e| Value::F64(*e), left, right, path)
+        }
+        (Value::LSTF32(a), Value::LSTF32(b)) => {
+            approx_eq_floats(a, b, |a, b| close_f32(*a, *b, tolerance), |e| Value::F64(f64::from(*e)), left, right, path)
+        }
+        (Value::LST(a), Value::LST(b)) => {
+            if a.len() != b.len() {
+                return mismatch(left, right);
+            }
+            for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+                path.push(PathSegment::Index(i));
+                let result = approx_eq(a, b, tolerance, path);
+                if result.is_some() {
+                    return result;
+                }
+                path.pop();
+            }
+            None
+        }
+        (Value::MAP(a), Value::MAP(b)) => approx_eq_maps(a, b, tolerance, path),
+        _ => if left == right { None } else { mismatch(left, right) },
+    }
+}
+
+fn approx_eq_maps(a: &Map<String, Value>, b: &Map<String, Value>, tolerance: Tolerance, path: &mut Path) -> Mismatch {
+    for key in sorted_keys(a, b) {
+        path.push(PathSegment::Key(key.clone()));
+        let result = match (a.get(key), b.get(key)) {
+            (Some(a), Some(b)) => approx_eq(a, b, tolerance, path),
+            (a, b) => Some((a.cloned(), b.cloned())),
+        };
+        if result.is_some() {
+            return result;
+        }
+        path.pop();
+    }
+    None
+}
+
+fn approx_eq_floats<T, F, V>(a: &[T], b: &[T], close: F, to_value: V, left: &Value, right: &Value, path: &mut Path) -> Mismatch
+    where F: Fn(&T, &T) -> bool, V: Fn(&T) -> Value {
+    if a.len() != b.len() {
+        return mismatch(left, right);
+    }
+    let index = a.iter().zip(b.iter()).position(|(a, b)| !close(a, b))?;
+    path.push(PathSegment::Index(index));
+    Some((Some(to_value(&a[index])), Some(to_value(&b[index]))))
+}
+
+fn close_f64(a: f64, b: f64, tolerance: Tolerance) -> bool {
+    if a == b || (a.is_nan() && b.is_nan()) {
+        return true;
+    }
+    if a.is_nan() || b.is_nan() || a.is_infinite() || b.is_infinite() {
+        return false;
+    }
+    match tolerance {
+        Tolerance::Absolute(tolerance) => (a - b).abs() <= tolerance,
+        Tolerance::Relative(tolerance) => (a - b).abs() <= tolerance * a.abs().max(b.abs()),
+        Tolerance::Ulps(ulps) => {
+            // Maps the bits onto a scale where consecutive floats are consecutive integers.
+            let ordered = |v: f64| {
+                let bits = v.to_bits() as i64;
+                if bits < 0 { i64::MIN as i128 - bits as i128 } else { bits as i128 }
+            };
+            (ordered(a) - ordered(b)).unsigned_abs() <= ulps as u128
+        }
+    }
+}
+
+fn close_f32(a: f32, b: f32, tolerance: Tolerance) -> bool {
+    match tolerance {
+        Tolerance::Ulps(ulps) => {
+            if a == b || (a.is_nan() && b.is_nan()) {
+                return true;
+            }
+            if a.is_nan() || b.is_nan() || a.is_infinite() || b.is_infinite() {
+                return false;
+            }
+            let ordered = |v: f32| {
+                let bits = v.to_bits() as i32;
+                if bits < 0 { i32::MIN as i64 - bits as i64 } else { bits as i64 }
+            };
+            (ordered(a) - ordered(b)).unsigned_abs() <= ulps
+        }
+        _ => close_f64(f64::from(a), f64::from(b), tolerance),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::Tolerance;
+    use Value;
+
+    #[test]
+    fn approx_eq() {
+        let a = tson!({"x": 1.0, "l": [f64[0.1, 0.2, 0.3]], "f": f32[1.0], "n": f64[f64::NAN]});
+        let b = tson!({"x": 1.0 + 1e-12, "l": [f64[0.1, 0.2, 0.1 + 0.2]], "f": f32[1.0], "n": f64[f64::NAN]});
+        assert!(a.approx_eq(&b, Tolerance::Absolute(1e-9)).is_ok());
+        assert!(a.approx_eq(&b, Tolerance::Relative(1e-9)).is_ok());
+        assert!(a.approx_eq(&b, Tolerance::Ulps(1)).is_err());
+
+        let error = a.approx_eq(&b, Tolerance::Ulps(1000)).unwrap_err();
+        assert_eq!(error.to_string(), "/x differs -- 1.0 != 1.000000000001");
+
+        let b = tson!({"x": 1.0, "l": [f64[0.1, 0.25, 0.3]], "f": f32[1.0], "n": f64[f64::NAN]});
+        let error = a.approx_eq(&b, Tolerance::Absolute(0.01)).unwrap_err();
+        assert_eq!(error.path.to_string(), "/l/0/1");
+        assert_eq!(error.left, Some(Value::F64(0.2)));
+        assert_eq!(error.right, Some(Value::F64(0.25)));
+    }
+
+    #[test]
+    fn approx_eq_structure() {
+        let a = tson!({"a": 1, "b": "x"});
+        let error = a.approx_eq(&tson!({"a": 1}), Tolerance::Absolute(1.0)).unwrap_err();
+        assert_eq!(error.to_string(), r#"/b differs -- "x" != missing"#);
+        let error = a.approx_eq(&tson!({"a": 1.0, "b": "x"}), Tolerance::Absolute(1.0)).unwrap_err();
+        assert_eq!(error.to_string(), "/a differs -- 1 != 1.0");
+        let error = tson!(f64[1.0]).approx_eq(&tson!(f64[1.0, 2.0]), Tolerance::Absolute(1.0)).unwrap_err();
+        assert_eq!(error.to_string(), "/ differs -- f64[1.0] != f64[1.0, 2.0]");
+    }
+}
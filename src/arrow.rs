@@ -0,0 +1,195 @@
+//! Conversions to and from Apache Arrow arrays and record batches, with the `arrow` feature.
+
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type,
+                         UInt16Type, UInt32Type, UInt64Type, UInt8Type};
+use arrow_array::{Array, ArrayRef, ArrowPrimitiveType, PrimitiveArray, RecordBatch, StringArray};
+use arrow_buffer::{Buffer, OffsetBuffer, ScalarBuffer};
+use arrow_schema::{DataType, Field, Schema};
+
+use ::{Map, StrVec, TsonError, TsonResult, Value};
+use spec::TsonTypedListType;
+use table::Table;
+
+impl Value {
+    /// Arrow array of a typed list, the primitive array taking over the vector without a copy,
+    /// or of a LSTSTR, the Utf8 array reusing its bytes once the terminators are removed.
+    pub fn into_arrow(self) -> TsonResult<ArrayRef> {
+        Ok(match self {
+            Value::LSTU8(v) => primitive::<UInt8Type>(v),
+            Value::LSTI8(v) => primitive::<Int8Type>(v),
+            Value::LSTU16(v) => primitive::<UInt16Type>(v),
+            Value::LSTI16(v) => primitive::<Int16Type>(v),
+            Value::LSTU32(v) => primitive::<UInt32Type>(v),
+            Value::LSTI32(v) => primitive::<Int32Type>(v),
+            Value::LSTU64(v) => primitive::<UInt64Type>(v),
+            Value::LSTI64(v) => primitive::<Int64Type>(v),
+            Value::LSTF32(v) => primitive::<Float32Type>(v),
+            Value::LSTF64(v) => primitive::<Float64Type>(v),
+            Value::LSTSTR(v) => Arc::new(string_array(v)?),
+            _ => return Err(TsonError::new(format!("arrow -- typed list expected -- found {}", self.tson_type()))),
+        })
+    }
+
+    /// Typed list of a primitive array, taking over its buffer when not shared nor sliced,
+    /// or LSTSTR of a Utf8 array.
+    ///
+    /// A null gives NaN in floats, and fails in integers and strings.
+    pub fn from_arrow(array: ArrayRef) -> TsonResult<Value> {
+        match *array.data_type() {
+            DataType::UInt8 => from_primitive::<UInt8Type>(array, None),
+            DataType::Int8 => from_primitive::<Int8Type>(array, None),
+            DataType::UInt16 => from_primitive::<UInt16Type>(array, None),
+            DataType::Int16 => from_primitive::<Int16Type>(array, None),
+            DataType::UInt32 => from_primitive::<UInt32Type>(array, None),
+            DataType::Int32 => from_primitive::<Int32Type>(array, None),
+            DataType::UInt64 => from_primitive::<UInt64Type>(array, None),
+            DataType::Int64 => from_primitive::<Int64Type>(array, None),
+            DataType::Float32 => from_primitive::<Float32Type>(array, Some(f32::NAN)),
+            DataType::Float64 => from_primitive::<Float64Type>(array, Some(f64::NAN)),
+            DataType::Utf8 => from_strings(array.as_string::<i32>()),
+            ref data_type => Err(TsonError::new(format!("arrow -- unsupported data type {}", data_type))),
+        }
+    }
+
+    /// Record batch of a MAP of columns, in the order of `Table::try_from`.
+    pub fn into_record_batch(self) -> TsonResult<RecordBatch> {
+        let columns = Table::try_from(self)?.into_columns();
+        let mut fields = Vec::with_capacity(columns.len());
+        let mut arrays = Vec::with_capacity(columns.len());
+        for (name, column) in columns {
+            let array = column.into_arrow().map_err(|e| TsonError::new(format!("column \"{}\" -- {}", name, e)))?;
+            fields.push(Field::new(name, array.data_type().clone(), false));
+            arrays.push(array);
+        }
+        let schema = Arc::new(Schema::new(fields));
+        if arrays.is_empty() {
+            return Ok(RecordBatch::new_empty(schema));
+        }
+        RecordBatch::try_new(schema, arrays).map_err(|e| TsonError::new(format!("arrow -- {}", e)))
+    }
+
+    /// MAP of the columns of a record batch, each converted as by `from_arrow`.
+    pub fn from_record_batch(batch: RecordBatch) -> TsonResult<Value> {
+        let (schema, arrays, _) = batch.into_parts();
+        schema.fields().iter().zip(arrays)
+            .map(|(field, array)| {
+                Value::from_arrow(array)
+                    .map(|column| (field.name().clone(), column))
+                    .map_err(|e| TsonError::new(format!("column \"{}\" -- {}", field.name(), e)))
+            })
+            .collect::<TsonResult<Map<_, _>>>()
+            .map(Value::MAP)
+    }
+}
+
+fn primitive<T>(vec: Vec<T::Native>) -> ArrayRef where T: ArrowPrimitiveType {
+    Arc::new(PrimitiveArray::<T>::new(ScalarBuffer::from(vec), None))
+}
+
+fn from_primitive<T>(array: ArrayRef, null: Option<T::Native>) -> TsonResult<Value>
+    where T: ArrowPrimitiveType, T::Native: TsonTypedListType {
+    let data = array.to_data();
+    // The buffers are only owned by `data` once the array is dropped, if not shared elsewhere.
+    drop(array);
+    let (_, values, nulls) = PrimitiveArray::<T>::from(data).into_parts();
+    let mut vec = values.into_inner().into_vec::<T::Native>()
+        .unwrap_or_else(|buffer| buffer.typed_data::<T::Native>().to_vec());
+    if let Some(nulls) = nulls.filter(|n| n.null_count() > 0) {
+        match null {
+            Some(null) => {
+                for (element, valid) in vec.iter_mut().zip(nulls.iter()) {
+                    if !valid {
+                        *element = null;
+                    }
+                }
+            }
+            None => {
+                let index = nulls.iter().position(|valid| !valid).unwrap_or(0);
+                return Err(TsonError::new(format!("arrow -- null at {} -- not held by {}", index, T::Native::tson_type())));
+            }
+        }
+    }
+    Ok(T::Native::into_value(vec))
+}
+
+fn string_array(strings: StrVec) -> TsonResult<StringArray> {
+    let mut bytes = strings.bytes;
+    let mut offsets = vec![0i32];
+    let mut len = 0usize;
+    for b in &bytes {
+        if *b == 0 {
+            let offset = i32::try_from(len).map_err(|_| TsonError::new("arrow -- LSTSTR too large for Utf8"))?;
+            offsets.push(offset);
+        } else {
+            len += 1;
+        }
+    }
+    bytes.retain(|b| *b != 0);
+    StringArray::try_new(OffsetBuffer::new(ScalarBuffer::from(offsets)), Buffer::from_vec(bytes), None)
+        .map_err(|e| TsonError::new(format!("arrow -- {}", e)))
+}
+
+fn from_strings(array: &StringArray) -> TsonResult<Value> {
+    let mut bytes = Vec::with_capacity(array.values().len() + array.len());
+    for (i, string) in array.iter().enumerate() {
+        let string = string.ok_or_else(|| TsonError::new(format!("arrow -- null at {} -- not held by LSTSTR", i)))?;
+        if string.contains('\0') {
+            return Err(TsonError::new(format!("arrow -- nul byte in string {}", i)));
+        }
+        bytes.extend_from_slice(string.as_bytes());
+        bytes.push(0);
+    }
+    Ok(Value::LSTSTR(StrVec::from_bytes_unchecked(bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{Array, ArrayRef, Float64Array, Int32Array, StringArray};
+
+    use Value;
+
+    #[test]
+    fn arrays() {
+        let vec = vec![1.0, 2.5];
+        let ptr = vec.as_ptr();
+        let array = Value::LSTF64(vec).into_arrow().unwrap();
+        assert_eq!(array.len(), 2);
+        match Value::from_arrow(array).unwrap() {
+            Value::LSTF64(v) => assert!(v == [1.0, 2.5] && v.as_ptr() == ptr),
+            v => panic!("{:?}", v),
+        }
+
+        for value in [tson!(u8[1, 2]), tson!(i64[-1]), tson!(f32[0.5]), tson!(str["a", "", "bc"]), tson!(str[])] {
+            assert_eq!(Value::from_arrow(value.clone().into_arrow().unwrap()).unwrap(), value);
+        }
+
+        let array: ArrayRef = Arc::new(StringArray::from(vec![Some("x"), None]));
+        assert_eq!(Value::from_arrow(array).unwrap_err().to_string(), "arrow -- null at 1 -- not held by LSTSTR");
+        let array: ArrayRef = Arc::new(StringArray::from(vec![Some("x"), Some("")]));
+        assert_eq!(Value::from_arrow(array).unwrap(), tson!(str["x", ""]));
+        let array: ArrayRef = Arc::new(Float64Array::from(vec![Some(1.0), None]));
+        assert!(matches!(Value::from_arrow(array).unwrap(), Value::LSTF64(ref v) if v[0] == 1.0 && v[1].is_nan()));
+        let array: ArrayRef = Arc::new(Int32Array::from(vec![Some(1), None]));
+        assert_eq!(Value::from_arrow(array).unwrap_err().to_string(), "arrow -- null at 1 -- not held by LSTI32");
+        let array: ArrayRef = Arc::new(Int32Array::from(vec![1, 2, 3]).slice(1, 2));
+        assert_eq!(Value::from_arrow(array).unwrap(), tson!(i32[2, 3]));
+        assert!(tson!([1]).into_arrow().is_err());
+    }
+
+    #[test]
+    fn record_batches() {
+        let value = tson!({"a": i32[1, 2], "b": str["x", "y"]});
+        let batch = value.clone().into_record_batch().unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.schema().field(1).name(), "b");
+        assert_eq!(Value::from_record_batch(batch).unwrap(), value);
+        assert!(tson!({"a": i32[1, 2], "b": str["x"]}).into_record_batch().is_err());
+        assert_eq!(tson!({"a": [1]}).into_record_batch().unwrap_err().to_string(), "column \"a\" -- typed list expected -- found LST");
+    }
+}
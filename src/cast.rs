@@ -0,0 +1,215 @@
+use std::fmt;
+
+use ::{TsonError, TsonResult, Value};
+use spec::{TsonType, TsonTypedListType};
+
+/// What a cast does with an element the target type cannot hold exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CastMode {
+    /// Fails on an element out of range, on a non integral float cast to integers, and on an integer
+    /// or a float not held exactly by the target float type.
+    Checked,
+    /// Clamps to the bounds of the target type, NaN giving 0 for integers, floats being truncated or rounded.
+    Saturating,
+    /// Keeps the low bits for integers, floats being truncated first, and rounds to the nearest float.
+    Wrapping,
+}
+
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Number::Int(v) => write!(f, "{}", v),
+            Number::Float(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+enum CastError {
+    OutOfRange,
+    PrecisionLoss,
+}
+
+trait Element: TsonTypedListType {
+    fn to_number(self) -> Number;
+    fn from_number(number: Number, mode: CastMode) -> Result<Self, CastError>;
+}
+
+macro_rules! int_element {
+    ($($t:ty),*) => {$(
+        impl Element for $t {
+            fn to_number(self) -> Number {
+                Number::Int(i128::from(self))
+            }
+
+            fn from_number(number: Number, mode: CastMode) -> Result<Self, CastError> {
+                let (min, max) = (i128::from(<$t>::MIN), i128::from(<$t>::MAX));
+                match (number, mode) {
+                    (Number::Int(v), CastMode::Checked) if v < min || v > max => Err(CastError::OutOfRange),
+                    (Number::Int(v), CastMode::Saturating) => Ok(v.max(min).min(max) as $t),
+                    (Number::Int(v), _) => Ok(v as $t),
+                    (Number::Float(v), CastMode::Checked) if v.is_nan() || v.trunc() != v => Err(CastError::PrecisionLoss),
+                    // max + 1 is a power of two, held exactly by an f64 unlike max for 64 bits types.
+                    (Number::Float(v), CastMode::Checked) if v < min as f64 || v >= (max + 1) as f64 => Err(CastError::OutOfRange),
+                    (Number::Float(v), CastMode::Wrapping) => Ok(v as i128 as $t),
+                    // Float to integer casts saturate.
+                    (Number::Float(v), _) => Ok(v as $t),
+                }
+            }
+        }
+    )*};
+}
+
+int_element!(u8, i8, u16, i16, u32, i32, u64, i64);
+
+impl Element for f32 {
+    fn to_number(self) -> Number {
+        Number::Float(f64::from(self))
+    }
+
+    fn from_number(number: Number, mode: CastMode) -> Result<Self, CastError> {
+        match (number, mode) {
+            (Number::Int(v), CastMode::Checked) if v as f32 as i128 != v => Err(CastError::PrecisionLoss),
+            (Number::Int(v), _) => Ok(v as f32),
+            (Number::Float(v), CastMode::Checked) if v.is_finite() && (v as f32).is_infinite() => Err(CastError::OutOfRange),
+            (Number::Float(v), CastMode::Checked) if !v.is_nan() && f64::from(v as f32) != v => Err(CastError::PrecisionLoss),
+            (Number::Float(v), CastMode::Saturating) if v.is_finite() => {
+                Ok(v.max(f64::from(f32::MIN)).min(f64::from(f32::MAX)) as f32)
+            }
+            (Number::Float(v), _) => Ok(v as f32),
+        }
+    }
+}
+
+impl Element for f64 {
+    fn to_number(self) -> Number {
+        Number::Float(self)
+    }
+
+    fn from_number(number: Number, mode: CastMode) -> Result<Self, CastError> {
+        match (number, mode) {
+            (Number::Int(v), CastMode::Checked) if v as f64 as i128 != v => Err(CastError::PrecisionLoss),
+            (Number::Int(v), _) => Ok(v as f64),
+            (Number::Float(v), _) => Ok(v),
+        }
+    }
+}
+
+impl Value {
+    /// Casts a numeric typed list into the numeric typed list `target`, element by element.
+    ///
+    /// A checked cast fails with the index of the first element the target type cannot hold exactly.
+    pub fn cast_typed_list(&self, target: TsonType, mode: CastMode) -> TsonResult<Value> {
+        if !target.is_typed_list() {
+            return Err(TsonError::new(format!("cast -- numeric typed list expected -- found {}", target)));
+        }
+        if self.tson_type() == target {
+            return Ok(self.clone());
+        }
+        match *self {
+            Value::LSTU8(ref v) => cast_from(v, target, mode),
+            Value::LSTI8(ref v) => cast_from(v, target, mode),
+            Value::LSTU16(ref v) => cast_from(v, target, mode),
+            Value::LSTI16(ref v) => cast_from(v, target, mode),
+            Value::LSTU32(ref v) => cast_from(v, target, mode),
+            Value::LSTI32(ref v) => cast_from(v, target, mode),
+            Value::LSTU64(ref v) => cast_from(v, target, mode),
+            Value::LSTI64(ref v) => cast_from(v, target, mode),
+            Value::LSTF32(ref v) => cast_from(v, target, mode),
+            Value::LSTF64(ref v) => cast_from(v, target, mode),
+            _ => Err(TsonError::new(format!("cast -- numeric typed list expected -- found {}", self.tson_type()))),
+        }
+    }
+}
+
+fn cast_from<S>(list: &[S], target: TsonType, mode: CastMode) -> TsonResult<Value> where S: Element {
+    match target {
+        TsonType::LSTU8 => cast::<S, u8>(list, mode),
+        TsonType::LSTI8 => cast::<S, i8>(list, mode),
+        TsonType::LSTU16 => cast::<S, u16>(list, mode),
+        TsonType::LSTI16 => cast::<S, i16>(list, mode),
+        TsonType::LSTU32 => cast::<S, u32>(list, mode),
+        TsonType::LSTI32 => cast::<S, i32>(list, mode),
+        TsonType::LSTU64 => cast::<S, u64>(list, mode),
+        TsonType::LSTI64 => cast::<S, i64>(list, mode),
+        TsonType::LSTF32 => cast::<S, f32>(list, mode),
+        _ => cast::<S, f64>(list, mode),
+    }
+}
+
+fn cast<S, T>(list: &[S], mode: CastMode) -> TsonResult<Value> where S: Element, T: Element {
+    let mut result = Vec::with_capacity(list.len());
+    for (i, element) in list.iter().enumerate() {
+        let number = element.to_number();
+        match T::from_number(number, mode) {
+            Ok(v) => result.push(v),
+            Err(e) => {
+                let reason = match e {
+                    CastError::OutOfRange => "out of range",
+                    CastError::PrecisionLoss => "loses precision",
+                };
+                return Err(TsonError::new(format!("cast to {} -- {} at {} {}", T::tson_type(), number, i, reason)));
+            }
+        }
+    }
+    Ok(T::into_value(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use cast::CastMode;
+    use spec::TsonType;
+    use Value;
+
+    #[test]
+    fn cast_integers() {
+        let value = Value::LSTI64(vec![1, -1, 3_000_000_000]);
+        assert_eq!(value.cast_typed_list(TsonType::LSTI32, CastMode::Checked).unwrap_err().to_string(),
+                   "cast to LSTI32 -- 3000000000 at 2 out of range");
+        assert_eq!(value.cast_typed_list(TsonType::LSTI32, CastMode::Saturating).unwrap(), Value::LSTI32(vec![1, -1, i32::MAX]));
+        assert_eq!(value.cast_typed_list(TsonType::LSTI32, CastMode::Wrapping).unwrap(), Value::LSTI32(vec![1, -1, -1_294_967_296]));
+        assert_eq!(value.cast_typed_list(TsonType::LSTU8, CastMode::Saturating).unwrap(), Value::LSTU8(vec![1, 0, 255]));
+        assert_eq!(value.cast_typed_list(TsonType::LSTF64, CastMode::Checked).unwrap(), Value::LSTF64(vec![1.0, -1.0, 3e9]));
+        assert_eq!(Value::LSTU64(vec![u64::MAX]).cast_typed_list(TsonType::LSTF64, CastMode::Checked).unwrap_err().to_string(),
+                   "cast to LSTF64 -- 18446744073709551615 at 0 loses precision");
+        assert!(value.cast_typed_list(TsonType::LSTSTR, CastMode::Checked).is_err());
+        assert!(Value::from("a").cast_typed_list(TsonType::LSTI32, CastMode::Checked).is_err());
+    }
+
+    #[test]
+    fn cast_floats() {
+        let value = Value::LSTF64(vec![1.0, 0.1, 1e300, -2.5]);
+        assert_eq!(value.cast_typed_list(TsonType::LSTF32, CastMode::Checked).unwrap_err().to_string(),
+                   "cast to LSTF32 -- 0.1 at 1 loses precision");
+        assert_eq!(value.cast_typed_list(TsonType::LSTF32, CastMode::Saturating).unwrap(),
+                   Value::LSTF32(vec![1.0, 0.1, f32::MAX, -2.5]));
+        assert_eq!(value.cast_typed_list(TsonType::LSTF32, CastMode::Wrapping).unwrap(),
+                   Value::LSTF32(vec![1.0, 0.1, f32::INFINITY, -2.5]));
+        assert_eq!(Value::LSTF64(vec![1e39]).cast_typed_list(TsonType::LSTF32, CastMode::Checked).unwrap_err().to_string(),
+                   "cast to LSTF32 -- 1000000000000000000000000000000000000000 at 0 out of range");
+        assert_eq!(value.cast_typed_list(TsonType::LSTI8, CastMode::Checked).unwrap_err().to_string(),
+                   "cast to LSTI8 -- 0.1 at 1 loses precision");
+        assert_eq!(value.cast_typed_list(TsonType::LSTI8, CastMode::Saturating).unwrap(), Value::LSTI8(vec![1, 0, 127, -2]));
+        assert_eq!(Value::LSTF32(vec![300.0, f32::NAN]).cast_typed_list(TsonType::LSTU8, CastMode::Wrapping).unwrap(),
+                   Value::LSTU8(vec![44, 0]));
+    }
+
+    #[test]
+    fn cast_float_bounds() {
+        let checked = |values: Vec<f64>, target| Value::LSTF64(values).cast_typed_list(target, CastMode::Checked);
+        let (p31, p63, p64) = (2f64.powi(31), 2f64.powi(63), 2f64.powi(64));
+        assert_eq!(checked(vec![p31 - 1.0, -p31], TsonType::LSTI32).unwrap(), Value::LSTI32(vec![i32::MAX, i32::MIN]));
+        assert_eq!(checked(vec![p31], TsonType::LSTI32).unwrap_err().to_string(), "cast to LSTI32 -- 2147483648 at 0 out of range");
+        assert_eq!(checked(vec![-p63], TsonType::LSTI64).unwrap(), Value::LSTI64(vec![i64::MIN]));
+        assert_eq!(checked(vec![p63], TsonType::LSTI64).unwrap_err().to_string(),
+                   "cast to LSTI64 -- 9223372036854776000 at 0 out of range");
+        assert_eq!(checked(vec![p64 - 2048.0], TsonType::LSTU64).unwrap(), Value::LSTU64(vec![u64::MAX - 2047]));
+        assert_eq!(checked(vec![p64], TsonType::LSTU64).unwrap_err().to_string(),
+                   "cast to LSTU64 -- 18446744073709552000 at 0 out of range");
+    }
+}
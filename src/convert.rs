@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::convert::{Infallible, TryFrom};
+use std::hash::BuildHasher;
+
+use ::{Map, StrVec, TsonError, TsonResult, Value};
+use spec::{TsonType, TsonTypedListType};
+
+impl From<Infallible> for TsonError {
+    fn from(value: Infallible) -> Self {
+        match value {}
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::I32(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::F64(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::BOOL(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::STR(value.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::STR(value)
+    }
+}
+
+impl<T> From<Vec<T>> for Value where T: TsonTypedListType {
+    fn from(value: Vec<T>) -> Self {
+        T::into_value(value)
+    }
+}
+
+impl From<Vec<String>> for Value {
+    fn from(value: Vec<String>) -> Self {
+        Value::LSTSTR(value.into())
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(value: Vec<Value>) -> Self {
+        Value::LST(value)
+    }
+}
+
+impl From<StrVec> for Value {
+    fn from(value: StrVec) -> Self {
+        Value::LSTSTR(value)
+    }
+}
+
+/// None gives NULL.
+impl<T> From<Option<T>> for Value where T: Into<Value> {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::NULL, |v| v.into())
+    }
+}
+
+impl<V, S> From<HashMap<String, V, S>> for Value where V: Into<Value>, S: BuildHasher {
+    fn from(value: HashMap<String, V, S>) -> Self {
+        Value::MAP(value.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+}
+
+#[cfg(feature = "preserve_order")]
+impl<V> From<::indexmap::IndexMap<String, V>> for Value where V: Into<Value> {
+    fn from(value: ::indexmap::IndexMap<String, V>) -> Self {
+        Value::MAP(value.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+}
+
+impl TryFrom<Value> for i32 {
+    type Error = TsonError;
+
+    fn try_from(value: Value) -> TsonResult<Self> {
+        value.as_i32()
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = TsonError;
+
+    fn try_from(value: Value) -> TsonResult<Self> {
+        value.as_f64()
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = TsonError;
+
+    fn try_from(value: Value) -> TsonResult<Self> {
+        value.as_bool()
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = TsonError;
+
+    fn try_from(value: Value) -> TsonResult<Self> {
+        match value {
+            Value::STR(v) => Ok(v),
+            _ => Err(value.type_error(TsonType::STR)),
+        }
+    }
+}
+
+impl<T> TryFrom<Value> for Vec<T> where T: TsonTypedListType {
+    type Error = TsonError;
+
+    fn try_from(value: Value) -> TsonResult<Self> {
+        T::try_from_value(value).map_err(|value| value.type_error(T::tson_type()))
+    }
+}
+
+impl TryFrom<Value> for Vec<String> {
+    type Error = TsonError;
+
+    fn try_from(value: Value) -> TsonResult<Self> {
+        match value {
+            Value::LSTSTR(v) => v.try_to_vec(),
+            _ => Err(value.type_error(TsonType::LSTSTR)),
+        }
+    }
+}
+
+impl TryFrom<Value> for Vec<Value> {
+    type Error = TsonError;
+
+    fn try_from(value: Value) -> TsonResult<Self> {
+        match value {
+            Value::LST(v) => Ok(v),
+            _ => Err(value.type_error(TsonType::LST)),
+        }
+    }
+}
+
+impl TryFrom<Value> for StrVec {
+    type Error = TsonError;
+
+    fn try_from(value: Value) -> TsonResult<Self> {
+        match value {
+            Value::LSTSTR(v) => Ok(v),
+            _ => Err(value.type_error(TsonType::LSTSTR)),
+        }
+    }
+}
+
+impl<V, S> TryFrom<Value> for HashMap<String, V, S>
+    where V: TryFrom<Value>, V::Error: Into<TsonError>, S: BuildHasher + Default {
+    type Error = TsonError;
+
+    fn try_from(value: Value) -> TsonResult<Self> {
+        match value {
+            Value::MAP(v) => v.into_iter()
+                .map(|(k, v)| V::try_from(v).map(|v| (k, v)).map_err(|e| e.into()))
+                .collect(),
+            _ => Err(value.type_error(TsonType::MAP)),
+        }
+    }
+}
+
+#[cfg(feature = "preserve_order")]
+impl<V> TryFrom<Value> for ::indexmap::IndexMap<String, V>
+    where V: TryFrom<Value>, V::Error: Into<TsonError> {
+    type Error = TsonError;
+
+    fn try_from(value: Value) -> TsonResult<Self> {
+        match value {
+            Value::MAP(v) => v.into_iter()
+                .map(|(k, v)| V::try_from(v).map(|v| (k, v)).map_err(|e| e.into()))
+                .collect(),
+            _ => Err(value.type_error(TsonType::MAP)),
+        }
+    }
+}
+
+// A generic impl over Option<T> would overlap with core's `TryFrom<U> for T where U: Into<T>`
+// through `From<T> for Option<T>`, hence one impl per type.
+macro_rules! try_from_option {
+    ($($t:ty),*) => {
+        $(
+            /// NULL gives None.
+            impl TryFrom<Value> for Option<$t> {
+                type Error = TsonError;
+
+                fn try_from(value: Value) -> TsonResult<Self> {
+                    if value.is_null() {
+                        Ok(None)
+                    } else {
+                        <$t>::try_from(value).map(Some)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+try_from_option!(i32, f64, bool, String, StrVec, Vec<Value>, Vec<String>,
+    Vec<u8>, Vec<i8>, Vec<u16>, Vec<i16>, Vec<u32>, Vec<i32>, Vec<u64>, Vec<i64>, Vec<f32>, Vec<f64>,
+    Map<String, Value>);
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::convert::{TryFrom, TryInto};
+    use {Map, TsonResult, Value};
+
+    #[test]
+    fn from() {
+        assert_eq!(Value::from(42), Value::I32(42));
+        assert_eq!(Value::from(4.2), Value::F64(4.2));
+        assert_eq!(Value::from(true), Value::BOOL(true));
+        assert_eq!(Value::from("a"), Value::STR("a".to_owned()));
+        assert_eq!(Value::from(vec![1u16, 2]), Value::LSTU16(vec![1, 2]));
+        assert_eq!(Value::from(vec![1.0f32]), Value::LSTF32(vec![1.0]));
+        assert_eq!(Value::from(vec!["a".to_owned()]), Value::LSTSTR(vec!["a".to_owned()].into()));
+        assert_eq!(Value::from(None::<i32>), Value::NULL);
+        assert_eq!(Value::from(Some("a")), Value::STR("a".to_owned()));
+
+        let mut map = HashMap::new();
+        map.insert("values".to_owned(), vec![1.0, 2.0]);
+        let mut expected = Map::new();
+        expected.insert("values".to_owned(), Value::LSTF64(vec![1.0, 2.0]));
+        assert_eq!(Value::from(map), Value::MAP(expected));
+    }
+
+    #[test]
+    fn try_from() -> TsonResult<()> {
+        assert_eq!(i32::try_from(Value::I32(42))?, 42);
+        assert_eq!(String::try_from(Value::STR("a".to_owned()))?, "a");
+        let values: Vec<i64> = Value::LSTI64(vec![1, 2]).try_into()?;
+        assert_eq!(values, vec![1, 2]);
+        let strings: Vec<String> = Value::from(vec!["a".to_owned(), "b".to_owned()]).try_into()?;
+        assert_eq!(strings, vec!["a", "b"]);
+        assert_eq!(Option::<f64>::try_from(Value::NULL)?, None);
+        assert_eq!(Option::<f64>::try_from(Value::F64(1.0))?, Some(1.0));
+
+        let error = Vec::<i32>::try_from(Value::LSTF64(vec![1.0])).unwrap_err();
+        assert_eq!(error.to_string(), "LSTI32 expected -- found LSTF64");
+
+        let value = tson!({"a": f64[1.0], "b": f64[]});
+        let map: HashMap<String, Vec<f64>> = value.clone().try_into()?;
+        assert_eq!(map["a"], vec![1.0]);
+        let map: HashMap<String, Value> = value.try_into()?;
+        assert_eq!(map["b"], Value::LSTF64(vec![]));
+        assert!(HashMap::<String, i32>::try_from(tson!({"a": "b"})).is_err());
+        Ok(())
+    }
+}
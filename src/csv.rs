@@ -0,0 +1,458 @@
+//! CSV and TSV import and export of tables, MAPs of columns.
+
+use std::convert::TryFrom;
+use std::io::{BufRead, Write};
+
+use ::{Map, StrVec, TsonError, TsonResult, Value};
+use spec::TsonType;
+use table::Table;
+
+/// When the writer quotes a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteStyle {
+    /// Fields holding the delimiter, the quote, a line break, or strings equal to the null token.
+    Necessary,
+    /// All fields but nulls.
+    Always,
+    /// No field, the caller knowing that none needs it.
+    Never,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub quote_style: QuoteStyle,
+    /// The first record names the columns, otherwise named `c0`, `c1`, ...
+    pub header: bool,
+    /// Unquoted field standing for a missing value.
+    pub null_token: String,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions { delimiter: b',', quote: b'"', quote_style: QuoteStyle::Necessary, header: true, null_token: String::new() }
+    }
+}
+
+impl CsvOptions {
+    pub fn tsv() -> Self {
+        CsvOptions { delimiter: b'\t', ..CsvOptions::default() }
+    }
+}
+
+fn csv_error<S>(line: usize, message: S) -> TsonError where S: AsRef<str> {
+    TsonError::new(format!("csv -- line {} -- {}", line, message.as_ref()))
+}
+
+/// Reads records one at a time, giving tables of a bounded number of rows.
+///
+/// A column of integers gives a LSTI32, or a LSTI64 when larger, of numbers a LSTF64 and any other column a LSTSTR.
+/// A null gives NaN, turning an integer column into a LSTF64, and turns a string column into a generic LST of STR
+/// and NULL, a LSTSTR having no null.
+///
+/// Column types are inferred from the first batch, later batches failing on a field their column cannot hold.
+pub struct CsvReader<R> {
+    reader: R,
+    options: CsvOptions,
+    names: Vec<String>,
+    types: Vec<TsonType>,
+    line: usize,
+    buf: Vec<u8>,
+}
+
+impl<R> CsvReader<R> where R: BufRead {
+    pub fn new(reader: R, options: CsvOptions) -> TsonResult<Self> {
+        let mut csv = CsvReader { reader, options, names: Vec::new(), types: Vec::new(), line: 0, buf: Vec::new() };
+        if csv.options.header {
+            if let Some(names) = csv.read_record()? {
+                for (i, name) in names.iter().enumerate() {
+                    if names[..i].contains(name) {
+                        return Err(csv_error(csv.line, format!("duplicate column \"{}\"", name.0)));
+                    }
+                }
+                csv.names = names.into_iter().map(|n| n.0).collect();
+            }
+        }
+        Ok(csv)
+    }
+
+    /// Column names, known once the header or the first record is read.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Fields of the next record, nulls being None.
+    pub fn next_record(&mut self) -> TsonResult<Option<Vec<Option<String>>>> {
+        let record = match self.read_record()? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        if self.names.is_empty() && !self.options.header {
+            self.names = (0..record.len()).map(|i| format!("c{}", i)).collect();
+        }
+        if record.len() != self.names.len() {
+            return Err(csv_error(self.line, format!("{} fields expected -- found {}", self.names.len(), record.len())));
+        }
+        let null_token = &self.options.null_token;
+        Ok(Some(record.into_iter().map(|(field, quoted)| if !quoted && field == *null_token { None } else { Some(field) }).collect()))
+    }
+
+    /// Column types, known once the first batch is read.
+    pub fn types(&self) -> &[TsonType] {
+        &self.types
+    }
+
+    /// MAP of columns of at most `rows` rows, None once all the records are read.
+    pub fn read_batch(&mut self, rows: usize) -> TsonResult<Option<Value>> {
+        let mut columns: Vec<Vec<Option<String>>> = Vec::new();
+        let mut lines = Vec::new();
+        while lines.len() < rows {
+            match self.next_record()? {
+                Some(record) => {
+                    columns.resize(record.len(), Vec::new());
+                    for (column, field) in columns.iter_mut().zip(record) {
+                        column.push(field);
+                    }
+                    lines.push(self.line);
+                }
+                None => break,
+            }
+        }
+        if lines.is_empty() {
+            return Ok(None);
+        }
+        if self.types.is_empty() {
+            self.types = columns.iter().map(|fields| infer(fields)).collect();
+        }
+        let mut map = Map::new();
+        for ((name, fields), tson_type) in self.names.iter().zip(columns).zip(self.types.iter()) {
+            map.insert(name.clone(), to_column(name, fields, *tson_type, &lines)?);
+        }
+        Ok(Some(Value::MAP(map)))
+    }
+
+    /// MAP of columns of all the remaining records.
+    pub fn read_all(&mut self) -> TsonResult<Value> {
+        match self.read_batch(usize::MAX)? {
+            Some(table) => Ok(table),
+            None => {
+                let types = self.types.iter().cloned().chain(::std::iter::repeat(TsonType::LSTSTR));
+                self.names.iter().zip(types)
+                    .map(|(name, tson_type)| to_column(name, Vec::new(), tson_type, &[]).map(|column| (name.clone(), column)))
+                    .collect::<TsonResult<Map<_, _>>>()
+                    .map(Value::MAP)
+            }
+        }
+    }
+
+    /// Fields of the next non blank record, with whether they were quoted.
+    fn read_record(&mut self) -> TsonResult<Option<Vec<(String, bool)>>> {
+        let (delimiter, quote) = (self.options.delimiter, self.options.quote);
+        let mut fields = Vec::new();
+        let mut field = Vec::new();
+        let mut quoted = false;
+        let mut in_quotes = false;
+        loop {
+            self.buf.clear();
+            let read = self.reader.read_until(b'\n', &mut self.buf).map_err(|e| TsonError::new(e.to_string()))?;
+            if read == 0 {
+                if in_quotes {
+                    return Err(csv_error(self.line, "unterminated quote"));
+                }
+                return Ok(None);
+            }
+            self.line += 1;
+            if !in_quotes && (self.buf == b"\n" || self.buf == b"\r\n") {
+                continue;
+            }
+            let mut bytes = self.buf.iter().cloned().peekable();
+            while let Some(b) = bytes.next() {
+                if in_quotes {
+                    if b != quote {
+                        field.push(b);
+                    } else if bytes.peek() == Some(&quote) {
+                        field.push(quote);
+                        bytes.next();
+                    } else {
+                        in_quotes = false;
+                    }
+                } else if b == quote && field.is_empty() && !quoted {
+                    in_quotes = true;
+                    quoted = true;
+                } else if b == delimiter {
+                    fields.push(self.field(&mut field, quoted)?);
+                    quoted = false;
+                } else if b == b'\n' || (b == b'\r' && bytes.peek() == Some(&b'\n')) {
+                    break;
+                } else {
+                    field.push(b);
+                }
+            }
+            if !in_quotes {
+                fields.push(self.field(&mut field, quoted)?);
+                return Ok(Some(fields));
+            }
+        }
+    }
+
+    fn field(&self, field: &mut Vec<u8>, quoted: bool) -> TsonResult<(String, bool)> {
+        let bytes = ::std::mem::take(field);
+        if bytes.contains(&0) {
+            return Err(csv_error(self.line, "nul byte in field"));
+        }
+        String::from_utf8(bytes).map(|s| (s, quoted)).map_err(|_| csv_error(self.line, "utf8 : bad string"))
+    }
+}
+
+fn infer(fields: &[Option<String>]) -> TsonType {
+    let present = || fields.iter().filter_map(|f| f.as_ref());
+    let has_nulls = fields.iter().any(|f| f.is_none());
+    if present().next().is_none() {
+        TsonType::LST
+    } else if !has_nulls && present().all(|f| f.parse::<i32>().is_ok()) {
+        TsonType::LSTI32
+    } else if !has_nulls && present().all(|f| f.parse::<i64>().is_ok()) {
+        TsonType::LSTI64
+    } else if present().all(|f| is_number(f)) {
+        TsonType::LSTF64
+    } else if has_nulls {
+        TsonType::LST
+    } else {
+        TsonType::LSTSTR
+    }
+}
+
+/// Column of type `tson_type`, `lines` being the lines of the fields.
+fn to_column(name: &str, fields: Vec<Option<String>>, tson_type: TsonType, lines: &[usize]) -> TsonResult<Value> {
+    let error = |i: usize| {
+        let field = fields[i].as_ref().map_or("null".to_owned(), |f| format!("{:?}", f));
+        csv_error(lines[i], format!("column \"{}\" -- {} not held by {}", name, field, tson_type))
+    };
+    fn parse_all<T, F>(fields: &[Option<String>], parse: F, error: &dyn Fn(usize) -> TsonError) -> TsonResult<Vec<T>>
+        where F: Fn(&Option<String>) -> Option<T> {
+        fields.iter().enumerate().map(|(i, field)| parse(field).ok_or_else(|| error(i))).collect()
+    }
+    match tson_type {
+        TsonType::LSTI32 => parse_all(&fields, |f| f.as_ref().and_then(|f| f.parse().ok()), &error).map(Value::LSTI32),
+        TsonType::LSTI64 => parse_all(&fields, |f| f.as_ref().and_then(|f| f.parse().ok()), &error).map(Value::LSTI64),
+        TsonType::LSTF64 => parse_all(&fields, |f| match *f {
+            None => Some(f64::NAN),
+            Some(ref f) if is_number(f) => f.parse().ok(),
+            Some(_) => None,
+        }, &error).map(Value::LSTF64),
+        TsonType::LST => Ok(Value::LST(fields.into_iter().map(|f| f.map_or(Value::NULL, Value::STR)).collect())),
+        _ => parse_all(&fields, |f| f.clone(), &error).map(|strings| Value::LSTSTR(StrVec::from(strings))),
+    }
+}
+
+/// Whether a field is a decimal number, `-1`, `.5`, `1.5e-3`, rather than one of the words, such as `inf`
+/// or `NaN`, also parsed by `f64::from_str`.
+fn is_number(field: &str) -> bool {
+    let bytes = field.as_bytes();
+    let mut i = 0;
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while *i < bytes.len() && bytes[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        *i - start
+    };
+    if i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') {
+        i += 1;
+    }
+    let mut mantissa = digits(&mut i);
+    if i < bytes.len() && bytes[i] == b'.' {
+        i += 1;
+        mantissa += digits(&mut i);
+    }
+    if mantissa == 0 {
+        return false;
+    }
+    if i < bytes.len() && (bytes[i] == b'e' || bytes[i] == b'E') {
+        i += 1;
+        if i < bytes.len() && (bytes[i] == b'+' || bytes[i] == b'-') {
+            i += 1;
+        }
+        if digits(&mut i) == 0 {
+            return false;
+        }
+    }
+    i == bytes.len()
+}
+
+/// Writes a MAP of columns, typed lists or LSTSTR, as records, columns in the order of `Table::try_from`.
+///
+/// Integers are written as is and floats in their shortest form keeping the fraction of integral
+/// values, read back as F64. NaN is written as the null token.
+pub fn write<W>(writer: &mut W, table: &Value, options: &CsvOptions) -> TsonResult<()> where W: Write {
+    let table = Table::try_from(table.clone())?;
+    let columns: Vec<(&str, &Value)> = table.columns().collect();
+    let starts = columns.iter()
+        .map(|&(_, column)| match *column {
+            Value::LSTSTR(ref strings) => strings.build_starts(),
+            _ => Ok(Vec::new()),
+        })
+        .collect::<TsonResult<Vec<_>>>()?;
+    let mut line = Vec::new();
+    let mut write_line = |writer: &mut W, fields: &mut dyn Iterator<Item = TsonResult<Option<String>>>| -> TsonResult<()> {
+        line.clear();
+        for (i, field) in fields.enumerate() {
+            if i > 0 {
+                line.push(options.delimiter);
+            }
+            if let Some(field) = field? {
+                write_field(&mut line, &field, options);
+            } else {
+                line.extend_from_slice(options.null_token.as_bytes());
+            }
+        }
+        line.push(b'\n');
+        writer.write_all(&line).map_err(|e| TsonError::new(e.to_string()))
+    };
+    if options.header {
+        write_line(writer, &mut columns.iter().map(|&(name, _)| Ok(Some(name.to_owned()))))?;
+    }
+    for row in 0..table.row_count() {
+        write_line(writer, &mut columns.iter().zip(starts.iter()).map(|(&(_, column), starts)| element(column, starts, row)))?;
+    }
+    Ok(())
+}
+
+/// Element `index` of a column, `starts` being the element starts of a LSTSTR.
+fn element(column: &Value, starts: &[usize], index: usize) -> TsonResult<Option<String>> {
+    Ok(Some(match *column {
+        Value::LSTU8(ref v) => v[index].to_string(),
+        Value::LSTI8(ref v) => v[index].to_string(),
+        Value::LSTU16(ref v) => v[index].to_string(),
+        Value::LSTI16(ref v) => v[index].to_string(),
+        Value::LSTU32(ref v) => v[index].to_string(),
+        Value::LSTI32(ref v) => v[index].to_string(),
+        Value::LSTU64(ref v) => v[index].to_string(),
+        Value::LSTI64(ref v) => v[index].to_string(),
+        Value::LSTF32(ref v) if v[index].is_nan() => return Ok(None),
+        Value::LSTF32(ref v) => format!("{:?}", v[index]),
+        Value::LSTF64(ref v) if v[index].is_nan() => return Ok(None),
+        Value::LSTF64(ref v) => format!("{:?}", v[index]),
+        Value::LSTSTR(ref v) => String::from_utf8(v.bytes[starts[index]..starts[index + 1] - 1].to_vec())
+            .map_err(|_| TsonError::new("utf8 : bad string"))?,
+        _ => return Err(TsonError::new(format!("csv -- typed list expected -- found {}", column.tson_type()))),
+    }))
+}
+
+fn write_field(line: &mut Vec<u8>, field: &str, options: &CsvOptions) {
+    let quote = match options.quote_style {
+        QuoteStyle::Always => true,
+        QuoteStyle::Never => false,
+        QuoteStyle::Necessary => field == options.null_token
+            || field.bytes().any(|b| b == options.delimiter || b == options.quote || b == b'\n' || b == b'\r'),
+    };
+    if !quote {
+        line.extend_from_slice(field.as_bytes());
+        return;
+    }
+    line.push(options.quote);
+    for b in field.bytes() {
+        if b == options.quote {
+            line.push(b);
+        }
+        line.push(b);
+    }
+    line.push(options.quote);
+}
+
+#[cfg(test)]
+mod tests {
+    use csv::{is_number, write, CsvOptions, CsvReader, QuoteStyle};
+    use spec::TsonType;
+    use Value;
+
+    #[test]
+    fn read() {
+        let data = "i,f,s,n\n1,1.5,a,\n\n2,,\"b,\"\"c\"\"\nd\",\r\n3,2,\"\",\n";
+        let mut reader = CsvReader::new(data.as_bytes(), CsvOptions::default()).unwrap();
+        assert_eq!(reader.names(), &["i", "f", "s", "n"]);
+        let table = reader.read_all().unwrap();
+        assert_eq!(table["i"], tson!(i32[1, 2, 3]));
+        assert_eq!(table["s"], tson!(str["a", "b,\"c\"\nd", ""]));
+        assert_eq!(table["n"], tson!([null, null, null]));
+        match table["f"] {
+            Value::LSTF64(ref v) => assert!(v[0] == 1.5 && v[1].is_nan() && v[2] == 2.0),
+            ref v => panic!("{:?}", v),
+        }
+
+        let options = CsvOptions { header: false, null_token: "NA".to_owned(), ..CsvOptions::tsv() };
+        let mut reader = CsvReader::new("1\tx\nNA\ty\n3\tNA\n".as_bytes(), options).unwrap();
+        let batch = reader.read_batch(2).unwrap().unwrap();
+        assert_eq!(reader.names(), &["c0", "c1"]);
+        assert_eq!(batch["c1"], tson!(str["x", "y"]));
+        assert!(matches!(batch["c0"], Value::LSTF64(ref v) if v[0] == 1.0 && v[1].is_nan()));
+        assert_eq!(reader.read_batch(2).unwrap_err().to_string(), "csv -- line 3 -- column \"c1\" -- null not held by LSTSTR");
+
+        let table = CsvReader::new("a,b\nx,1\n,2\n\"\",3\n".as_bytes(), CsvOptions::default()).unwrap().read_all().unwrap();
+        assert_eq!(table["a"], tson!(["x", null, ""]));
+        let mut reader = CsvReader::new("a,b\n,1\nx,2\n".as_bytes(), CsvOptions::default()).unwrap();
+        assert_eq!(reader.read_batch(1).unwrap().unwrap(), tson!({"a": [null], "b": i32[1]}));
+        assert_eq!(reader.read_batch(1).unwrap().unwrap(), tson!({"a": ["x"], "b": i32[2]}));
+    }
+
+    #[test]
+    fn read_batches() {
+        let mut reader = CsvReader::new("a\n1\nx\n".as_bytes(), CsvOptions::default()).unwrap();
+        assert_eq!(reader.read_batch(1).unwrap().unwrap(), tson!({"a": i32[1]}));
+        assert_eq!(reader.types(), &[TsonType::LSTI32]);
+        assert_eq!(reader.read_batch(1).unwrap_err().to_string(), "csv -- line 3 -- column \"a\" -- \"x\" not held by LSTI32");
+
+        let mut reader = CsvReader::new("a\n1.5\n\n".as_bytes(), CsvOptions::default()).unwrap();
+        reader.read_batch(1).unwrap();
+        assert_eq!(reader.read_all().unwrap(), tson!({"a": f64[]}));
+    }
+
+    #[test]
+    fn read_integers() {
+        let table = CsvReader::new("a\n9007199254740993\n-1\n".as_bytes(), CsvOptions::default()).unwrap().read_all().unwrap();
+        assert_eq!(table["a"], tson!(i64[9_007_199_254_740_993, -1]));
+
+        let table = CsvReader::new("a,b\n1.5e3,inf\n-.5,1\n".as_bytes(), CsvOptions::default()).unwrap().read_all().unwrap();
+        assert_eq!(table["a"], tson!(f64[1500.0, -0.5]));
+        assert_eq!(table["b"], tson!(str["inf", "1"]));
+        for word in ["NaN", "infinity", "-Inf", "1e", ".", "+", "1.5x"] {
+            assert!(!is_number(word), "{}", word);
+        }
+    }
+
+    #[test]
+    fn read_errors() {
+        let mut reader = CsvReader::new("a,b\n1\n".as_bytes(), CsvOptions::default()).unwrap();
+        assert_eq!(reader.read_all().unwrap_err().to_string(), "csv -- line 2 -- 2 fields expected -- found 1");
+        let mut reader = CsvReader::new("a\n\"1\n".as_bytes(), CsvOptions::default()).unwrap();
+        assert_eq!(reader.read_all().unwrap_err().to_string(), "csv -- line 2 -- unterminated quote");
+        assert!(CsvReader::new("a,a\n".as_bytes(), CsvOptions::default()).is_err());
+    }
+
+    #[test]
+    fn write_read() {
+        let table = tson!({"a": i32[1, 2], "b": f64[1.0, f64::NAN], "c": str["x,y", ""]});
+        let mut out = Vec::new();
+        write(&mut out, &table, &CsvOptions::default()).unwrap();
+        assert_eq!(String::from_utf8(out.clone()).unwrap(), "a,b,c\n1,1.0,\"x,y\"\n2,,\"\"\n");
+
+        let read = CsvReader::new(&out[..], CsvOptions::default()).unwrap().read_all().unwrap();
+        assert_eq!(read["a"], table["a"]);
+        assert_eq!(read["c"], table["c"]);
+
+        let mut out = Vec::new();
+        let options = CsvOptions { quote_style: QuoteStyle::Always, header: false, ..CsvOptions::tsv() };
+        write(&mut out, &tson!({"a": i32[1], "c": str["x"]}), &options).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\"1\"\t\"x\"\n");
+
+        let table = tson!({"u": u32[3_000_000_000, 1], "v": i64[9_007_199_254_740_993, -1], "w": f32[0.1, 1.0]});
+        let mut out = Vec::new();
+        write(&mut out, &table, &CsvOptions { header: false, ..CsvOptions::default() }).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "3000000000,9007199254740993,0.1\n1,-1,1.0\n");
+
+        let mut out = Vec::new();
+        assert_eq!(write(&mut out, &tson!({"a": i32[1], "b": f64[]}), &CsvOptions::default()).unwrap_err().to_string(),
+                   "column \"b\" -- length 1 expected -- found 0");
+    }
+}
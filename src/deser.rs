@@ -1,5 +1,11 @@
+use std::cell::{Cell, RefCell};
+#[cfg(not(feature = "preserve_order"))]
+use std::collections::hash_map::Entry;
+#[cfg(feature = "preserve_order")]
+use indexmap::map::Entry;
 use std::io::Read;
-use std::ptr::slice_from_raw_parts_mut;
+use std::convert::TryFrom;
+use std::mem::size_of;
 use std::slice;
 use byteorder::{ByteOrder, LittleEndian};
 
@@ -31,15 +37,24 @@ pub trait Reader {
 
     fn read_string(&mut self) -> TsonResult<String>;
     fn read_string_lossy(&mut self) -> TsonResult<String>;
+
+    /// Reads a null terminated string without decoding it, the terminator is consumed but not returned.
+    fn read_cstring(&mut self) -> TsonResult<Vec<u8>> {
+        let mut vec = Vec::new();
+        loop {
+            let byte = self.read_u8()?;
+            if byte == 0 {
+                return Ok(vec);
+            }
+            vec.push(byte);
+        }
+    }
 }
 
 
 impl<T> Reader for T where T: Read {
     fn read_all(&mut self, buf: &mut Vec<u8>) -> TsonResult<()> {
-        let bytes = self.bytes();
-        for byte in bytes {
-            buf.push(byte?);
-        }
+        self.read_to_end(buf)?;
         Ok(())
     }
 
@@ -186,16 +201,7 @@ impl<T> Reader for T where T: Read {
     }
 
     fn read_string(&mut self) -> TsonResult<String> {
-        let mut done = false;
-        let mut vec = Vec::new();
-        while !done {
-            let byte = self.read_u8()?;
-            if byte == 0 {
-                done = true;
-            } else {
-                vec.push(byte);
-            }
-        }
+        let vec = self.read_cstring()?;
 
         if let Ok(value) = String::from_utf8(vec) {
             Ok(value)
@@ -205,32 +211,113 @@ impl<T> Reader for T where T: Read {
     }
 
     fn read_string_lossy(&mut self) -> TsonResult<String> {
-        let mut done = false;
-        let mut vec = Vec::new();
-        while !done {
-            let byte = self.read_u8()?;
-            if byte == 0 {
-                done = true;
-            } else {
-                vec.push(byte);
-            }
-        }
+        let vec = self.read_cstring()?;
 
         Ok(String::from_utf8_lossy(&vec).to_string())
     }
 }
 
+/// A string that was not valid utf8 and has been replaced while decoding in lossy mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Utf8Warning {
+    /// Path of the value holding the string, string lists add the index of the string.
+    pub path: Path,
+    /// Offset of the first byte of the string from the first byte read by the decoder.
+    pub offset: usize,
+    /// Original bytes of the string, without the terminator.
+    pub bytes: Vec<u8>,
+}
+
+/// What to do when a map holds the same key more than once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKeys {
+    /// Fails the decoding.
+    Error,
+    /// Keeps the first entry.
+    FirstWins,
+    /// Keeps the last entry.
+    #[default]
+    LastWins,
+    /// Keeps the last entry and records a `DuplicateKey`.
+    Collect,
+}
+
+/// A map key found more than once while decoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateKey {
+    /// Path of the duplicated entry.
+    pub path: Path,
+    /// Offset of the first byte of the repeated key from the first byte read by the decoder.
+    pub offset: usize,
+}
+
 pub struct Deserializer {
     lossy_utf8: bool,
+    collect_warnings: bool,
+    duplicate_keys: DuplicateKeys,
+    offset: Cell<usize>,
+    path: RefCell<Path>,
+    warnings: RefCell<Vec<Utf8Warning>>,
+    duplicates: RefCell<Vec<DuplicateKey>>,
 }
 
 impl Deserializer {
-    pub fn new(lossy_utf8: bool) -> Deserializer { Deserializer {lossy_utf8} }
+    pub fn new(lossy_utf8: bool) -> Deserializer {
+        Deserializer {
+            lossy_utf8,
+            collect_warnings: false,
+            duplicate_keys: DuplicateKeys::LastWins,
+            offset: Cell::new(0),
+            path: RefCell::new(Path::new()),
+            warnings: RefCell::new(Vec::new()),
+            duplicates: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// In lossy mode, records a `Utf8Warning` for every replaced string, see `take_warnings`.
+    pub fn collect_warnings(mut self, collect_warnings: bool) -> Deserializer {
+        self.collect_warnings = collect_warnings;
+        self
+    }
+
+    pub fn duplicate_keys(mut self, duplicate_keys: DuplicateKeys) -> Deserializer {
+        self.duplicate_keys = duplicate_keys;
+        self
+    }
+
+    // Used by gdeser to decode a value in the middle of a stream.
+    pub(crate) fn resume(self, offset: usize, path: Path) -> Deserializer {
+        self.offset.set(offset);
+        self.path.replace(path);
+        self
+    }
 
+    fn tracks_path(&self) -> bool {
+        self.collect_warnings || self.duplicate_keys != DuplicateKeys::LastWins
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset.get()
+    }
+
+    pub fn take_warnings(&self) -> Vec<Utf8Warning> {
+        self.warnings.take()
+    }
+
+    pub fn take_duplicates(&self) -> Vec<DuplicateKey> {
+        self.duplicates.take()
+    }
+
+    /// Decodes a whole document, offsets, warnings and duplicates of a previous call being discarded.
     pub fn read(&self, reader: &mut dyn Reader) -> TsonResult<Value> {
+        self.offset.set(0);
+        self.path.replace(Path::new());
+        self.warnings.borrow_mut().clear();
+        self.duplicates.borrow_mut().clear();
+
         let itype = self.read_type(reader)?;
 
-        if itype != STRING_TYPE {
+        if itype != TsonType::STR {
             return Err(TsonError::new("wrong format -- expect version as str"));
         }
 
@@ -243,129 +330,169 @@ impl Deserializer {
         self.read_object(reader)
     }
 
-    fn read_type(&self, reader: &mut dyn Reader) -> TsonResult<u8> {
-        reader.read_u8()
+    fn advance(&self, len: usize) {
+        self.offset.set(self.offset.get() + len);
+    }
+
+    fn read_type(&self, reader: &mut dyn Reader) -> TsonResult<TsonType> {
+        let itype = reader.read_u8()?;
+        self.advance(1);
+        TsonType::try_from(itype)
     }
 
     fn read_len(&self, reader: &mut dyn Reader) -> TsonResult<usize> {
-        Ok(reader.read_u32()? as usize)
+        let len = reader.read_u32()? as usize;
+        self.advance(4);
+        Ok(len)
     }
 
     fn read_string(&self, reader: &mut dyn Reader) -> TsonResult<String> {
-        if self.lossy_utf8 {
-            reader.read_string_lossy()
-        } else {
-            reader.read_string()
+        let offset = self.offset.get();
+        let bytes = reader.read_cstring()?;
+        self.advance(bytes.len() + 1);
+
+        match String::from_utf8(bytes) {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                if !self.lossy_utf8 {
+                    return Err(TsonError::new("bad string"));
+                }
+                let value = String::from_utf8_lossy(e.as_bytes()).into_owned();
+                if self.collect_warnings {
+                    let path = self.path.borrow().clone();
+                    self.warnings.borrow_mut().push(Utf8Warning { path, offset, bytes: e.into_bytes() });
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    fn read_str_vec(&self, reader: &mut dyn Reader) -> TsonResult<StrVec> {
+        let len_in_bytes = self.read_len(reader)?;
+        let offset = self.offset.get();
+        let mut bytes = vec![0; len_in_bytes];
+        reader.read_u8_into(&mut bytes)?;
+        self.advance(len_in_bytes);
+
+        if !self.lossy_utf8 {
+            return StrVec::from_bytes(bytes);
+        }
+
+        if self.collect_warnings {
+            let path = self.path.borrow();
+            let mut warnings = self.warnings.borrow_mut();
+            for (index, range) in StrVec::invalid_utf8_strings(&bytes) {
+                warnings.push(Utf8Warning {
+                    path: path.index(index),
+                    offset: offset + range.start,
+                    bytes: bytes[range].to_vec(),
+                });
+            }
+        }
+        StrVec::from_bytes_lossy(bytes)
+    }
+
+    fn on_duplicate(&self, offset: usize) -> TsonResult<()> {
+        match self.duplicate_keys {
+            DuplicateKeys::Error => Err(TsonError::new(format!("wrong format -- MAP -- duplicate key {}", self.path.borrow()))),
+            DuplicateKeys::Collect => {
+                let path = self.path.borrow().clone();
+                self.duplicates.borrow_mut().push(DuplicateKey { path, offset });
+                Ok(())
+            }
+            DuplicateKeys::FirstWins | DuplicateKeys::LastWins => Ok(()),
         }
     }
 
+    fn read_typed<T>(&self, reader: &mut dyn Reader) -> TsonResult<Vec<T>> where T: TsonTypedListType {
+        let len = self.read_len(reader)?;
+        let mut vec = vec![T::default(); len];
+        T::read_into(reader, &mut vec)?;
+        self.advance(len * size_of::<T>());
+        Ok(vec)
+    }
+
+    /// Reads a typed list of T, failing if the next object is anything else.
+    pub fn read_typed_list<T>(&self, reader: &mut dyn Reader) -> TsonResult<Vec<T>> where T: TsonTypedListType {
+        let itype = self.read_type(reader)?;
+        if itype != T::tson_type() {
+            return Err(TsonError::new(format!("wrong format -- {} expected -- found {}", T::tson_type(), itype)));
+        }
+        self.read_typed(reader)
+    }
+
     pub fn read_object(&self, reader: &mut dyn Reader) -> TsonResult<Value> {
         let itype = self.read_type(reader)?;
         match itype {
-            NULL_TYPE => Ok(Value::NULL),
-            STRING_TYPE => Ok(Value::STR(self.read_string(reader)?)),
-            INTEGER_TYPE => {
+            TsonType::NULL => Ok(Value::NULL),
+            TsonType::STR => Ok(Value::STR(self.read_string(reader)?)),
+            TsonType::I32 => {
+                self.advance(4);
                 Ok(Value::I32(reader.read_i32()?))
             }
-            DOUBLE_TYPE => {
+            TsonType::F64 => {
+                self.advance(8);
                 Ok(Value::F64(reader.read_f64()?))
             }
-            BOOL_TYPE => {
+            TsonType::BOOL => {
+                self.advance(1);
                 Ok(Value::BOOL(reader.read_u8()? > 0))
             }
-            LIST_TYPE => {
+            TsonType::LST => {
                 let len = self.read_len(reader)?;
                 let mut vec = Vec::with_capacity(len);
-                for _ in 0..len {
+                for i in 0..len {
+                    if self.tracks_path() {
+                        self.path.borrow_mut().push(PathSegment::Index(i));
+                    }
                     vec.push(self.read_object(reader)?);
+                    if self.tracks_path() {
+                        self.path.borrow_mut().pop();
+                    }
                 }
                 Ok(Value::LST(vec))
             }
-            MAP_TYPE => {
+            TsonType::MAP => {
                 let len = self.read_len(reader)?;
-                let mut map = HashMap::with_capacity(len);
+                let mut map = Map::with_capacity(len);
                 for _ in 0..len {
+                    let offset = self.offset.get() + 1;
                     if let Value::STR(k) = self.read_object(reader)? {
-                        map.insert(k, self.read_object(reader)?);
+                        if self.tracks_path() {
+                            self.path.borrow_mut().push(PathSegment::Key(k.clone()));
+                        }
+                        let value = self.read_object(reader)?;
+                        match map.entry(k) {
+                            Entry::Occupied(mut entry) => {
+                                self.on_duplicate(offset)?;
+                                if self.duplicate_keys != DuplicateKeys::FirstWins {
+                                    entry.insert(value);
+                                }
+                            }
+                            Entry::Vacant(entry) => {
+                                entry.insert(value);
+                            }
+                        }
+                        if self.tracks_path() {
+                            self.path.borrow_mut().pop();
+                        }
                     } else {
-                        return Err(TsonError::new("wrong format -- MAP_TYPE -- expected STR"));
+                        return Err(TsonError::new("wrong format -- MAP -- expected STR"));
                     }
                 }
                 Ok(Value::MAP(map))
             }
-            LIST_UINT8_TYPE => {
-                let len = self.read_len(reader)?;
-                let mut vec = vec![0; len];
-                reader.read_u8_into(&mut vec)?;
-                Ok(Value::LSTU8(vec))
-            }
-            LIST_INT8_TYPE => {
-                let len = self.read_len(reader)?;
-                let mut vec = vec![0; len];
-                reader.read_i8_into(&mut vec)?;
-                Ok(Value::LSTI8(vec))
-            }
-            LIST_UINT16_TYPE => {
-                let len = self.read_len(reader)?;
-                let mut vec = vec![0; len];
-                reader.read_u16_into(&mut vec)?;
-                Ok(Value::LSTU16(vec))
-            }
-            LIST_INT16_TYPE => {
-                let len = self.read_len(reader)?;
-                let mut vec = vec![0; len];
-                reader.read_i16_into(&mut vec)?;
-                Ok(Value::LSTI16(vec))
-            }
-
-            LIST_UINT32_TYPE => {
-                let len = self.read_len(reader)?;
-                let mut vec = vec![0; len];
-                reader.read_u32_into(&mut vec)?;
-                Ok(Value::LSTU32(vec))
-            }
-            LIST_INT32_TYPE => {
-                let len = self.read_len(reader)?;
-                let mut vec = vec![0; len];
-                reader.read_i32_into(&mut vec)?;
-                Ok(Value::LSTI32(vec))
-            }
-            LIST_INT64_TYPE => {
-                let len = self.read_len(reader)?;
-                let mut vec = vec![0; len];
-                reader.read_i64_into(&mut vec)?;
-                Ok(Value::LSTI64(vec))
-            }
-            LIST_UINT64_TYPE => {
-                let len = self.read_len(reader)?;
-                let mut vec = vec![0; len];
-                reader.read_u64_into(&mut vec)?;
-                Ok(Value::LSTU64(vec))
-            }
-            LIST_FLOAT32_TYPE => {
-                let len = self.read_len(reader)?;
-                let mut vec = vec![0.0; len];
-                reader.read_f32_into(&mut vec)?;
-                Ok(Value::LSTF32(vec))
-            }
-            LIST_FLOAT64_TYPE => {
-                let len = self.read_len(reader)?;
-                let mut vec = vec![0.0; len];
-                reader.read_f64_into(&mut vec)?;
-                Ok(Value::LSTF64(vec))
-            }
-            LIST_STRING_TYPE => {
-                let mut len_in_bytes = self.read_len(reader)?;
-                let mut bytes = vec![0;len_in_bytes];
-                reader.read_u8_into(&mut bytes)?;
-                if self.lossy_utf8 {
-                    Ok(Value::LSTSTR(StrVec::from_bytes_lossy(bytes)?))
-                } else {
-                    Ok(Value::LSTSTR(StrVec::from_bytes(bytes)?))
-                }
-            }
-
-            _ => Err(TsonError::new("wrong format -- _")),
+            TsonType::LSTU8 => Ok(Value::LSTU8(self.read_typed(reader)?)),
+            TsonType::LSTI8 => Ok(Value::LSTI8(self.read_typed(reader)?)),
+            TsonType::LSTU16 => Ok(Value::LSTU16(self.read_typed(reader)?)),
+            TsonType::LSTI16 => Ok(Value::LSTI16(self.read_typed(reader)?)),
+            TsonType::LSTU32 => Ok(Value::LSTU32(self.read_typed(reader)?)),
+            TsonType::LSTI32 => Ok(Value::LSTI32(self.read_typed(reader)?)),
+            TsonType::LSTI64 => Ok(Value::LSTI64(self.read_typed(reader)?)),
+            TsonType::LSTU64 => Ok(Value::LSTU64(self.read_typed(reader)?)),
+            TsonType::LSTF32 => Ok(Value::LSTF32(self.read_typed(reader)?)),
+            TsonType::LSTF64 => Ok(Value::LSTF64(self.read_typed(reader)?)),
+            TsonType::LSTSTR => Ok(Value::LSTSTR(self.read_str_vec(reader)?)),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,240 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+
+use ::{sorted_keys, Map, Path, PathSegment, Value};
+use spec::TsonType;
+
+/// A difference between two values, as reported by `diff`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Map key only found in the new value.
+    Added { path: Path, value: Value },
+    /// Map key only found in the old value.
+    Removed { path: Path, value: Value },
+    TypeChanged { path: Path, from: TsonType, to: TsonType },
+    /// Scalar or string with a different value.
+    Changed { path: Path, from: Value, to: Value },
+    /// List of a different length, its common elements being compared as well.
+    LengthChanged { path: Path, from: usize, to: usize },
+    /// Index ranges of the typed list elements that differ, within the common length.
+    Elements { path: Path, ranges: Vec<Range<usize>> },
+}
+
+/// Changes turning `a` into `b`, ordered by path with map keys sorted.
+///
+/// Floats are compared bitwise so NaN equals NaN.
+pub fn diff(a: &Value, b: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_values(a, b, &mut Path::new(), &mut changes);
+    changes
+}
+
+impl Change {
+    pub fn path(&self) -> &Path {
+        match *self {
+            Change::Added { ref path, .. }
+            | Change::Removed { ref path, .. }
+            | Change::TypeChanged { ref path, .. }
+            | Change::Changed { ref path, .. }
+            | Change::LengthChanged { ref path, .. }
+            | Change::Elements { ref path, .. } => path,
+        }
+    }
+
+    /// Map with an `op` and a `path` in pointer syntax, plus the fields of the change.
+    pub fn to_value(&self) -> Value {
+        let mut map = Map::new();
+        map.insert("path".to_owned(), Value::STR(self.path().to_string()));
+        let op = match *self {
+            Change::Added { ref value, .. } => {
+                map.insert("value".to_owned(), value.clone());
+                "added"
+            }
+            Change::Removed { ref value, .. } => {
+                map.insert("value".to_owned(), value.clone());
+                "removed"
+            }
+            Change::TypeChanged { from, to, .. } => {
+                map.insert("from".to_owned(), Value::STR(from.to_string()));
+                map.insert("to".to_owned(), Value::STR(to.to_string()));
+                "type_changed"
+            }
+            Change::Changed { ref from, ref to, .. } => {
+                map.insert("from".to_owned(), from.clone());
+                map.insert("to".to_owned(), to.clone());
+                "changed"
+            }
+            Change::LengthChanged { from, to, .. } => {
+                map.insert("from".to_owned(), len_value(from));
+                map.insert("to".to_owned(), len_value(to));
+                "length_changed"
+            }
+            Change::Elements { ref ranges, .. } => {
+                let bounds = ranges.iter().flat_map(|r| vec![r.start as u64, r.end as u64]).collect();
+                map.insert("ranges".to_owned(), Value::LSTU64(bounds));
+                "elements"
+            }
+        };
+        map.insert("op".to_owned(), Value::STR(op.to_owned()));
+        Value::MAP(map)
+    }
+}
+
+fn len_value(len: usize) -> Value {
+    match i32::try_from(len) {
+        Ok(len) => Value::I32(len),
+        Err(_) => Value::F64(len as f64),
+    }
+}
+
+/// One line per change, `~ /a/b: 1 -> 2`.
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = self.path().to_display_string();
+        match *self {
+            Change::Added { ref value, .. } => write!(f, "+ {}: {}", path, value),
+            Change::Removed { ref value, .. } => write!(f, "- {}: {}", path, value),
+            Change::TypeChanged { from, to, .. } => write!(f, "~ {}: type {} -> {}", path, from, to),
+            Change::Changed { ref from, ref to, .. } => write!(f, "~ {}: {} -> {}", path, from, to),
+            Change::LengthChanged { from, to, .. } => write!(f, "~ {}: length {} -> {}", path, from, to),
+            Change::Elements { ref ranges, .. } => {
+                let count: usize = ranges.iter().map(|r| r.len()).sum();
+                write!(f, "~ {}: {} elements differ in ", path, count)?;
+                for (i, range) in ranges.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}..{}", range.start, range.end)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn diff_values(a: &Value, b: &Value, path: &mut Path, changes: &mut Vec<Change>) {
+    match (a, b) {
+        (Value::MAP(a), Value::MAP(b)) => diff_maps(a, b, path, changes),
+        (Value::LST(a), Value::LST(b)) => {
+            if a.len() != b.len() {
+                changes.push(Change::LengthChanged { path: path.clone(), from: a.len(), to: b.len() });
+            }
+            for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+                path.push(PathSegment::Index(i));
+                diff_values(a, b, path, changes);
+                path.pop();
+            }
+        }
+        (Value::LSTU8(a), Value::LSTU8(b)) => diff_typed(a, b, |a, b| a == b, path, changes),
+        (Value::LSTI8(a), Value::LSTI8(b)) => diff_typed(a, b, |a, b| a == b, path, changes),
+        (Value::LSTU16(a), Value::LSTU16(b)) => diff_typed(a, b, |a, b| a == b, path, changes),
+        (Value::LSTI16(a), Value::LSTI16(b)) => diff_typed(a, b, |a, b| a == b, path, changes),
+        (Value::LSTU32(a), Value::LSTU32(b)) => diff_typed(a, b, |a, b| a == b, path, changes),
+        (Value::LSTI32(a), Value::LSTI32(b)) => diff_typed(a, b, |a, b| a == b, path, changes),
+        (Value::LSTU64(a), Value::LSTU64(b)) => diff_typed(a, b, |a, b| a == b, path, changes),
+        (Value::LSTI64(a), Value::LSTI64(b)) => diff_typed(a, b, |a, b| a == b, path, changes),
+        (Value::LSTF32(a), Value::LSTF32(b)) => diff_typed(a, b, |a, b| a.to_bits() == b.to_bits(), path, changes),
+        (Value::LSTF64(a), Value::LSTF64(b)) => diff_typed(a, b, |a, b| a.to_bits() == b.to_bits(), path, changes),
+        (Value::LSTSTR(a), Value::LSTSTR(b)) => {
+            let a: Vec<&[u8]> = a.bytes.split(|b| *b == 0).collect();
+            let b: Vec<&[u8]> = b.bytes.split(|b| *b == 0).collect();
+            // The last split is the empty slice after the final terminator.
+            diff_typed(&a[..a.len() - 1], &b[..b.len() - 1], |a, b| a == b, path, changes)
+        }
+        _ if a.tson_type() != b.tson_type() => {
+            changes.push(Change::TypeChanged { path: path.clone(), from: a.tson_type(), to: b.tson_type() });
+        }
+        _ => {
+            if a.total_cmp(b) != Ordering::Equal {
+                changes.push(Change::Changed { path: path.clone(), from: a.clone(), to: b.clone() });
+            }
+        }
+    }
+}
+
+fn diff_maps(a: &Map<String, Value>, b: &Map<String, Value>, path: &mut Path, changes: &mut Vec<Change>) {
+    for key in sorted_keys(a, b) {
+        path.push(PathSegment::Key(key.clone()));
+        match (a.get(key), b.get(key)) {
+            (Some(a), Some(b)) => diff_values(a, b, path, changes),
+            (Some(a), None) => changes.push(Change::Removed { path: path.clone(), value: a.clone() }),
+            (None, Some(b)) => changes.push(Change::Added { path: path.clone(), value: b.clone() }),
+            (None, None) => unreachable!(),
+        }
+        path.pop();
+    }
+}
+
+fn diff_typed<T, F>(a: &[T], b: &[T], eq: F, path: &Path, changes: &mut Vec<Change>) where F: Fn(&T, &T) -> bool {
+    if a.len() != b.len() {
+        changes.push(Change::LengthChanged { path: path.clone(), from: a.len(), to: b.len() });
+    }
+    let ranges = differing_ranges(a, b, eq);
+    if !ranges.is_empty() {
+        changes.push(Change::Elements { path: path.clone(), ranges });
+    }
+}
+
+/// Ranges of the differing elements within the common length.
+pub(crate) fn differing_ranges<T, F>(a: &[T], b: &[T], eq: F) -> Vec<Range<usize>> where F: Fn(&T, &T) -> bool {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+        if eq(a, b) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(ref mut range) if range.end == i => range.end += 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use diff::{diff, Change};
+    use spec::TsonType;
+    use {Path, Value};
+
+    #[test]
+    fn diff_values() {
+        let a = tson!({"a": 1, "b": "x", "c": [1, 2], "d": {"e": null}, "n": f64::NAN});
+        let b = tson!({"a": 2, "c": [1.0, 2, 3], "d": {"e": null, "f": true}, "n": f64::NAN});
+        let changes: Vec<String> = diff(&a, &b).iter().map(|c| c.to_string()).collect();
+        assert_eq!(changes, vec![
+            "~ /a: 1 -> 2",
+            "- /b: \"x\"",
+            "~ /c: length 2 -> 3",
+            "~ /c/0: type I32 -> F64",
+            "+ /d/f: true",
+        ]);
+        assert!(diff(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn diff_typed_lists() {
+        let a = Value::LSTF64((0..1_000_000).map(|i| i as f64).collect());
+        let mut values: Vec<f64> = (0..1_000_001).map(|i| i as f64).collect();
+        for i in (10..20).chain(500_000..500_001) {
+            values[i] = -1.0;
+        }
+        let b = Value::LSTF64(values);
+        let changes = diff(&a, &b);
+        assert_eq!(changes.iter().map(|c| c.to_string()).collect::<Vec<_>>(), vec![
+            "~ /: length 1000000 -> 1000001",
+            "~ /: 11 elements differ in 10..20, 500000..500001",
+        ]);
+        assert_eq!(changes[0].to_value(), tson!({"op": "length_changed", "path": "", "from": 1000000, "to": 1000001}));
+        assert_eq!(changes[1].to_value(), tson!({"op": "elements", "path": "", "ranges": u64[10, 20, 500000, 500001]}));
+        let change = Change::LengthChanged { path: Path::new(), from: 1, to: 1 << 31 };
+        assert_eq!(change.to_value()["to"], Value::F64(2147483648.0));
+
+        let changes = diff(&tson!(str["a", "b", "c"]), &tson!(str["a", "x", "c"]));
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].to_string(), "~ /: 1 elements differ in 1..2");
+        let changes = diff(&tson!(u8[]), &tson!(i8[]));
+        assert_eq!(changes[0].to_value()["to"], Value::from(TsonType::LSTI8.to_string()));
+    }
+}
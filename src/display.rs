@@ -0,0 +1,147 @@
+use std::fmt;
+
+use ::{StrVec, Value};
+
+/// Limits applied when displaying a `Value`, so that large documents can be logged safely.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayOptions {
+    /// Elements shown per list or map, the others are counted.
+    pub max_elements: usize,
+    /// Nesting level below which lists and maps are only counted.
+    pub max_depth: usize,
+    /// Characters shown per string.
+    pub max_string_len: usize,
+    /// Shows map keys in sorted order, for a stable output.
+    pub sort_keys: bool,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        DisplayOptions { max_elements: 10, max_depth: 8, max_string_len: 80, sort_keys: true }
+    }
+}
+
+/// Displays a value with the `tson!` syntax, `f64[1.0, 2.0, … 9999998 more]`.
+pub struct ValueDisplay<'a> {
+    value: &'a Value,
+    options: DisplayOptions,
+}
+
+impl Value {
+    pub fn display(&self, options: DisplayOptions) -> ValueDisplay<'_> {
+        ValueDisplay { value: self, options }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.display(DisplayOptions::default()).fmt(f)
+    }
+}
+
+impl<'a> fmt::Display for ValueDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_value(f, self.value, 0)
+    }
+}
+
+impl<'a> ValueDisplay<'a> {
+    fn write_value(&self, f: &mut fmt::Formatter, value: &Value, depth: usize) -> fmt::Result {
+        match *value {
+            Value::NULL => write!(f, "null"),
+            Value::STR(ref v) => self.write_str(f, v),
+            Value::I32(v) => write!(f, "{}", v),
+            Value::F64(v) => write!(f, "{:?}", v),
+            Value::BOOL(v) => write!(f, "{}", v),
+            Value::LST(ref v) => {
+                self.write_elements(f, ["[", "]"], v.len(), depth, v.iter(), |f, e| self.write_value(f, e, depth + 1))
+            }
+            Value::MAP(ref v) => {
+                let mut entries: Vec<(&String, &Value)> = v.iter().collect();
+                if self.options.sort_keys {
+                    entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                }
+                self.write_elements(f, ["{", "}"], entries.len(), depth, entries.into_iter(), |f, (k, v)| {
+                    self.write_str(f, k)?;
+                    write!(f, ": ")?;
+                    self.write_value(f, v, depth + 1)
+                })
+            }
+            Value::LSTU8(ref v) => self.write_typed(f, "u8", v, depth),
+            Value::LSTI8(ref v) => self.write_typed(f, "i8", v, depth),
+            Value::LSTU16(ref v) => self.write_typed(f, "u16", v, depth),
+            Value::LSTI16(ref v) => self.write_typed(f, "i16", v, depth),
+            Value::LSTU32(ref v) => self.write_typed(f, "u32", v, depth),
+            Value::LSTI32(ref v) => self.write_typed(f, "i32", v, depth),
+            Value::LSTU64(ref v) => self.write_typed(f, "u64", v, depth),
+            Value::LSTI64(ref v) => self.write_typed(f, "i64", v, depth),
+            Value::LSTF32(ref v) => self.write_typed(f, "f32", v, depth),
+            Value::LSTF64(ref v) => self.write_typed(f, "f64", v, depth),
+            Value::LSTSTR(ref v) => self.write_str_vec(f, v, depth),
+        }
+    }
+
+    fn write_typed<T>(&self, f: &mut fmt::Formatter, name: &str, v: &[T], depth: usize) -> fmt::Result where T: fmt::Debug {
+        write!(f, "{}", name)?;
+        self.write_elements(f, ["[", "]"], v.len(), depth, v.iter(), |f, e| write!(f, "{:?}", e))
+    }
+
+    fn write_str_vec(&self, f: &mut fmt::Formatter, v: &StrVec, depth: usize) -> fmt::Result {
+        let len = v.bytes.iter().filter(|b| **b == 0).count();
+        let strings = v.bytes.split(|b| *b == 0).take(len);
+        write!(f, "str")?;
+        self.write_elements(f, ["[", "]"], len, depth, strings, |f, e| self.write_str(f, &String::from_utf8_lossy(e)))
+    }
+
+    fn write_str(&self, f: &mut fmt::Formatter, v: &str) -> fmt::Result {
+        match v.char_indices().nth(self.options.max_string_len) {
+            Some((end, _)) => write!(f, "{:?}", format!("{}…", &v[..end])),
+            None => write!(f, "{:?}", v),
+        }
+    }
+
+    fn write_elements<I, F>(&self, f: &mut fmt::Formatter, brackets: [&str; 2], len: usize, depth: usize, elements: I, write: F) -> fmt::Result
+        where I: Iterator, F: Fn(&mut fmt::Formatter, I::Item) -> fmt::Result {
+        let shown = if depth < self.options.max_depth { len.min(self.options.max_elements) } else { 0 };
+        write!(f, "{}", brackets[0])?;
+        for (i, e) in elements.take(shown).enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write(f, e)?;
+        }
+        if shown < len {
+            if shown > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "… {} more", len - shown)?;
+        }
+        write!(f, "{}", brackets[1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use display::DisplayOptions;
+    use Value;
+
+    #[test]
+    fn display() {
+        let value = tson!({"b": [null, true, 1, 2.5], "a": "x", "c": str["a", "b"], "d": u8[]});
+        assert_eq!(value.to_string(), r#"{"a": "x", "b": [null, true, 1, 2.5], "c": str["a", "b"], "d": u8[]}"#);
+    }
+
+    #[test]
+    fn truncation() {
+        let value = Value::LSTF64(vec![0.0, 1.0, 2.0]);
+        let options = DisplayOptions { max_elements: 2, ..DisplayOptions::default() };
+        assert_eq!(value.display(options.clone()).to_string(), "f64[0.0, 1.0, … 1 more]");
+
+        let value = tson!([[[1, 2]], "abcdef"]);
+        let options = DisplayOptions { max_depth: 2, max_string_len: 3, ..options };
+        assert_eq!(value.display(options.clone()).to_string(), r#"[[[… 2 more]], "abc…"]"#);
+
+        let value = Value::LSTSTR(vec!["é".repeat(5); 3].into());
+        assert_eq!(value.display(options).to_string(), r#"str["ééé…", "ééé…", … 1 more]"#);
+    }
+}
@@ -1,18 +1,29 @@
 use ::{Reader, TsonResult};
-use ::{MAP_TYPE, TsonError};
-use ::{STRING_TYPE, VERSION};
-use ::{LIST_TYPE, Value};
+use ::{TsonError, VERSION};
+use ::Value;
+use ::{DuplicateKey, DuplicateKeys, Path, PathSegment, Utf8Warning};
 use Deserializer;
+use spec::{TsonType, TsonTypedListType};
+use std::convert::TryFrom;
+use std::collections::HashSet;
 
 pub struct TsonGDeserializer {
     reader: Box<dyn Reader>,
     lossy_utf8: bool,
+    collect_warnings: bool,
+    duplicate_keys: DuplicateKeys,
+    offset: usize,
+    path: Path,
+    warnings: Vec<Utf8Warning>,
+    duplicates: Vec<DuplicateKey>,
 }
 
 pub struct TsonMapDeser<'a> {
     deser: &'a mut TsonGDeserializer,
     len: usize,
     current: usize,
+    depth: usize,
+    keys: HashSet<String>,
 }
 
 pub struct TsonMapEntryDeser<'a> {
@@ -24,18 +35,20 @@ pub struct TsonListDeser<'a> {
     deser: &'a mut TsonGDeserializer,
     len: usize,
     current: usize,
+    depth: usize,
 }
 
+#[allow(dead_code)]
 pub struct TsonTypedListDeser<'a> {
     deser: &'a mut TsonGDeserializer,
 }
 
-impl<'a> TsonGDeserializer {
+impl TsonGDeserializer {
 
     pub fn new(lossy_utf8: bool, mut reader: Box<dyn Reader>) -> TsonResult<Self> {
         let itype = reader.read_u8()?;
 
-        if itype != STRING_TYPE {
+        if itype != TsonType::STR.code() {
             return Err(TsonError::new("wrong format -- expect version as str"));
         }
 
@@ -45,36 +58,99 @@ impl<'a> TsonGDeserializer {
             return Err(TsonError::new("wrong version"));
         }
 
-        Ok(TsonGDeserializer {reader, lossy_utf8})
+        Ok(TsonGDeserializer {
+            reader,
+            lossy_utf8,
+            collect_warnings: false,
+            duplicate_keys: DuplicateKeys::LastWins,
+            offset: 1 + version.len() + 1,
+            path: Path::new(),
+            warnings: Vec::new(),
+            duplicates: Vec::new(),
+        })
     }
 
-    pub fn read_type(&mut self) -> TsonResult<u8> {
-        self.reader.read_u8()
+    /// In lossy mode, records a `Utf8Warning` for every replaced string, see `take_warnings`.
+    pub fn collect_warnings(mut self, collect_warnings: bool) -> Self {
+        self.collect_warnings = collect_warnings;
+        self
+    }
+
+    /// Map entries are streamed, so `LastWins` yields every entry and leaves the choice to the caller,
+    /// `FirstWins` skips the repeated ones.
+    pub fn duplicate_keys(mut self, duplicate_keys: DuplicateKeys) -> Self {
+        self.duplicate_keys = duplicate_keys;
+        self
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Utf8Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn take_duplicates(&mut self) -> Vec<DuplicateKey> {
+        std::mem::take(&mut self.duplicates)
+    }
+
+    fn tracks_path(&self) -> bool {
+        self.collect_warnings || self.duplicate_keys != DuplicateKeys::LastWins
+    }
+
+    pub fn read_type(&mut self) -> TsonResult<TsonType> {
+        let itype = self.reader.read_u8()?;
+        self.offset += 1;
+        TsonType::try_from(itype)
     }
 
     pub fn read_len(&mut self) -> TsonResult<usize> {
-        Ok(self.reader.read_u32()? as usize)
+        let len = self.reader.read_u32()? as usize;
+        self.offset += 4;
+        Ok(len)
     }
 
     fn read_string(&mut self) -> TsonResult<String> {
-        if self.lossy_utf8 {
-            self.reader.read_string_lossy()
-        } else {
-            self.reader.read_string()
+        let offset = self.offset;
+        let bytes = self.reader.read_cstring()?;
+        self.offset += bytes.len() + 1;
+
+        match String::from_utf8(bytes) {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                if !self.lossy_utf8 {
+                    return Err(TsonError::new("bad string"));
+                }
+                let value = String::from_utf8_lossy(e.as_bytes()).into_owned();
+                if self.collect_warnings {
+                    self.warnings.push(Utf8Warning { path: self.path.clone(), offset, bytes: e.into_bytes() });
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    // Moves the current path to the child `segment` of the container at `depth`.
+    fn enter<F>(&mut self, depth: usize, segment: F) where F: FnOnce() -> PathSegment {
+        if self.tracks_path() {
+            self.path.truncate(depth);
+            self.path.push(segment());
         }
     }
 
     pub fn next_string(&mut self) -> TsonResult<String> {
-        if self.read_type()? == STRING_TYPE {
+        let t = self.read_type()?;
+        if t == TsonType::STR {
             self.read_string()
         } else {
-            Err(TsonError::new("TsonDeser -- bad type -- String expected"))
+            Err(TsonError::new(format!("TsonDeser -- bad type -- STR expected -- found {}", t)))
         }
     }
 
-    pub fn next_map(&mut self) -> TsonResult<TsonMapDeser> {
+    pub fn next_map(&mut self) -> TsonResult<TsonMapDeser<'_>> {
         let t = self.read_type()?;
-        if t == MAP_TYPE {
+        if t == TsonType::MAP {
             TsonMapDeser::new(self)
         } else {
             Err(TsonError::new(format!("bad type -- MAP expected -- found {}", t)))
@@ -82,31 +158,54 @@ impl<'a> TsonGDeserializer {
 
     }
 
-    pub fn next_list(&mut self) -> TsonResult<TsonListDeser> {
-        if self.read_type()? == LIST_TYPE {
+    pub fn next_list(&mut self) -> TsonResult<TsonListDeser<'_>> {
+        let t = self.read_type()?;
+        if t == TsonType::LST {
             TsonListDeser::new(self)
         } else {
-            Err(TsonError::new("bad type -- LIST expected"))
+            Err(TsonError::new(format!("bad type -- LST expected -- found {}", t)))
         }
     }
 
+    pub fn next_typed_list<T>(&mut self) -> TsonResult<Vec<T>> where T: TsonTypedListType {
+        let deser = Deserializer::new(self.lossy_utf8).resume(self.offset, self.path.clone());
+        let vec = deser.read_typed_list(self.reader.as_mut());
+        self.offset = deser.offset();
+        vec
+    }
+
     pub fn next_value(&mut self) -> TsonResult<Value> {
-        Deserializer::new(self.lossy_utf8).read_object(self.reader.as_mut())
+        let deser = Deserializer::new(self.lossy_utf8)
+            .collect_warnings(self.collect_warnings)
+            .duplicate_keys(self.duplicate_keys)
+            .resume(self.offset, self.path.clone());
+        let value = deser.read_object(self.reader.as_mut());
+        self.offset = deser.offset();
+        self.warnings.extend(deser.take_warnings());
+        self.duplicates.extend(deser.take_duplicates());
+        value
     }
 }
 
 impl<'a> TsonListDeser<'a> {
     pub fn new(deser: &'a mut TsonGDeserializer) -> TsonResult<Self> {
         let len = deser.read_len()?;
-        Ok(TsonListDeser{deser, len, current: 0 })
+        let depth = deser.path.len();
+        Ok(TsonListDeser{deser, len, current: 0, depth })
     }
 
     pub fn len(&self) -> usize {
         self.len
     }
 
-    pub fn next_map(&mut self) -> Option<TsonResult<TsonMapDeser>> {
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn next_map(&mut self) -> Option<TsonResult<TsonMapDeser<'_>>> {
         if self.current < self.len() {
+            let index = self.current;
+            self.deser.enter(self.depth, || PathSegment::Index(index));
             self.current += 1;
             Some(self.deser.next_map())
         } else {
@@ -114,8 +213,10 @@ impl<'a> TsonListDeser<'a> {
         }
     }
 
-    pub fn next_list(&mut self) -> Option<TsonResult<TsonListDeser>> {
+    pub fn next_list(&mut self) -> Option<TsonResult<TsonListDeser<'_>>> {
         if self.current < self.len() {
+            let index = self.current;
+            self.deser.enter(self.depth, || PathSegment::Index(index));
             self.current += 1;
             Some(self.deser.next_list())
         } else {
@@ -125,6 +226,8 @@ impl<'a> TsonListDeser<'a> {
 
     pub fn value(&mut self) -> Option<TsonResult<Value>> {
         if self.current < self.len() {
+            let index = self.current;
+            self.deser.enter(self.depth, || PathSegment::Index(index));
             self.current += 1;
             Some(self.deser.next_value())
         } else {
@@ -136,26 +239,61 @@ impl<'a> TsonListDeser<'a> {
 impl<'a> TsonMapDeser<'a> {
     pub fn new(deser: &'a mut TsonGDeserializer) -> TsonResult<Self> {
         let len = deser.read_len()?;
-        Ok(TsonMapDeser{deser, len, current: 0 })
+        let depth = deser.path.len();
+        Ok(TsonMapDeser{deser, len, current: 0, depth, keys: HashSet::new() })
     }
 
     pub fn len(&self) -> usize {
         self.len
     }
 
-    pub fn next(&mut self) -> Option<TsonResult<TsonMapEntryDeser>> {
-        if self.current < self.len() {
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<TsonResult<TsonMapEntryDeser<'_>>> {
+        while self.current < self.len() {
             self.current += 1;
-            Some(TsonMapEntryDeser::new(  &mut self.deser))
-        } else {
-            None
+            if self.deser.tracks_path() {
+                self.deser.path.truncate(self.depth);
+            }
+            let offset = self.deser.offset + 1;
+            let key = match self.deser.next_string() {
+                Ok(key) => key,
+                Err(e) => return Some(Err(e)),
+            };
+            self.deser.enter(self.depth, || PathSegment::Key(key.clone()));
+
+            if self.deser.duplicate_keys != DuplicateKeys::LastWins && !self.keys.insert(key.clone()) {
+                match self.deser.duplicate_keys {
+                    DuplicateKeys::Error => {
+                        return Some(Err(TsonError::new(format!("bad format -- MAP -- duplicate key {}", self.deser.path))));
+                    }
+                    DuplicateKeys::FirstWins => {
+                        if let Err(e) = self.deser.next_value() {
+                            return Some(Err(e));
+                        }
+                        continue;
+                    }
+                    DuplicateKeys::Collect => {
+                        let path = self.deser.path.clone();
+                        self.deser.duplicates.push(DuplicateKey { path, offset });
+                    }
+                    DuplicateKeys::LastWins => {}
+                }
+            }
+            return Some(Ok(TsonMapEntryDeser{deser: self.deser, key}));
         }
+        None
     }
 }
 
 impl<'a> TsonMapEntryDeser<'a> {
     pub fn new(deser: &'a mut TsonGDeserializer) -> TsonResult<Self> {
         let key = deser.next_string()?;
+        let depth = deser.path.len();
+        deser.enter(depth, || PathSegment::Key(key.clone()));
         Ok(TsonMapEntryDeser{deser, key})
     }
 
@@ -167,11 +305,11 @@ impl<'a> TsonMapEntryDeser<'a> {
         self.deser.next_string()
     }
 
-    pub fn map(&mut self) -> TsonResult<TsonMapDeser> {
+    pub fn map(&mut self) -> TsonResult<TsonMapDeser<'_>> {
         self.deser.next_map()
     }
 
-    pub fn list(&mut self) -> TsonResult<TsonListDeser> {
+    pub fn list(&mut self) -> TsonResult<TsonListDeser<'_>> {
         self.deser.next_list()
     }
 
@@ -183,19 +321,18 @@ impl<'a> TsonMapEntryDeser<'a> {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
     use std::io::Cursor;
-    use ::{encode, Value};
+    use ::{encode, DuplicateKeys, Map, Value, STRING_TYPE, MAP_TYPE, INTEGER_TYPE, VERSION};
     use gdeser::{TsonGDeserializer, TsonMapEntryDeser};
     use TsonResult;
 
     #[test]
     fn next_map() -> TsonResult<()>{
-        let mut map = HashMap::new();
+        let mut map = Map::new();
         map.insert("name".to_string(), Value::STR("factor1".to_string()));
         map.insert("values".to_string(), Value::LSTF64(vec![0.0,42.0]));
         let bytes = encode(&Value::MAP(map)).unwrap();
-        let mut reader = Box::new(Cursor::new(bytes));
+        let reader = Box::new(Cursor::new(bytes));
 
         let mut deser = TsonGDeserializer::new(true,reader)?;
 
@@ -210,7 +347,7 @@ mod tests {
                 if let Value::LSTF64(vec) = key_value.value()? {
                     assert_eq!(vec, vec![0.0,42.0]);
                 } else {
-                    assert!(false)
+                    panic!("LSTF64 expected")
                 }
             }
             Ok(())
@@ -222,18 +359,28 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn next_typed_list() -> TsonResult<()> {
+        let bytes = encode(&Value::LST(vec![Value::LSTF32(vec![1.0]), Value::LSTU8(vec![1])])).unwrap();
+        let mut deser = TsonGDeserializer::new(false, Box::new(Cursor::new(bytes)))?;
+        assert_eq!(deser.next_list()?.len(), 2);
+        assert_eq!(deser.next_typed_list::<f32>()?, vec![1.0]);
+        assert!(deser.next_typed_list::<i8>().is_err());
+        Ok(())
+    }
+
     #[test]
     fn next_list() -> TsonResult<()>{
-        let mut map1 = HashMap::new();
+        let mut map1 = Map::new();
         map1.insert("name".to_string(), Value::STR("factor1".to_string()));
         map1.insert("values".to_string(), Value::LSTF64(vec![0.0,42.0]));
 
-        let mut map2 = HashMap::new();
+        let mut map2 = Map::new();
         map2.insert("name".to_string(), Value::STR("factor2".to_string()));
         map2.insert("values".to_string(), Value::LSTF64(vec![0.0,42.0]));
 
         let bytes = encode(&Value::LST(vec![Value::MAP(map1), Value::MAP(map2)])).unwrap();
-        let mut reader = Box::new(Cursor::new(bytes));
+        let reader = Box::new(Cursor::new(bytes));
         let mut deser = TsonGDeserializer::new(false,reader)?;
 
         let mut deser_list = deser.next_list()?;
@@ -247,7 +394,7 @@ mod tests {
                 if let Value::LSTF64(vec) = key_value.value()? {
                     assert_eq!(vec, vec![0.0,42.0]);
                 } else {
-                    assert!(false)
+                    panic!("LSTF64 expected")
                 }
             }
             Ok(())
@@ -263,4 +410,66 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn lossy_warnings() -> TsonResult<()> {
+        let mut map = Map::new();
+        map.insert("name".to_string(), Value::STR("factor#".to_string()));
+        map.insert("values".to_string(), Value::LST(vec![Value::STR("#".to_string())]));
+        let mut bytes = encode(&Value::LST(vec![Value::MAP(map)])).unwrap();
+        bytes.iter_mut().filter(|b| **b == b'#').for_each(|b| *b = 0xff);
+        let len = bytes.len();
+
+        let mut deser = TsonGDeserializer::new(true, Box::new(Cursor::new(bytes.clone())))?.collect_warnings(true);
+        {
+            let mut list = deser.next_list()?;
+            let mut map = list.next_map().unwrap()?;
+            while let Some(entry) = map.next() {
+                let mut entry = entry?;
+                if entry.key() == "name" {
+                    assert_eq!(entry.string()?, "factor\u{FFFD}");
+                } else {
+                    entry.value()?;
+                }
+            }
+        }
+        assert_eq!(deser.offset(), len);
+
+        let mut warnings = deser.take_warnings();
+        warnings.sort_by_key(|w| w.path.to_string());
+        let paths: Vec<String> = warnings.iter().map(|w| w.path.to_string()).collect();
+        assert_eq!(paths, vec!["/0/name", "/0/values/0"]);
+        for w in warnings.iter() {
+            assert_eq!(&bytes[w.offset..w.offset + w.bytes.len()], &w.bytes[..]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_keys() -> TsonResult<()> {
+        let mut bytes = vec![STRING_TYPE];
+        bytes.extend_from_slice(VERSION.as_bytes());
+        bytes.extend_from_slice(&[0, MAP_TYPE, 2, 0, 0, 0]);
+        for i in 1..3 {
+            bytes.extend_from_slice(&[STRING_TYPE, b'a', 0, INTEGER_TYPE, i, 0, 0, 0]);
+        }
+
+        let read = |policy| -> TsonResult<(Vec<Value>, usize)> {
+            let mut deser = TsonGDeserializer::new(false, Box::new(Cursor::new(bytes.clone())))?.duplicate_keys(policy);
+            let mut values = Vec::new();
+            {
+                let mut map = deser.next_map()?;
+                while let Some(entry) = map.next() {
+                    values.push(entry?.value()?);
+                }
+            }
+            Ok((values, deser.take_duplicates().len()))
+        };
+
+        assert_eq!(read(DuplicateKeys::LastWins)?, (vec![Value::I32(1), Value::I32(2)], 0));
+        assert_eq!(read(DuplicateKeys::FirstWins)?, (vec![Value::I32(1)], 0));
+        assert_eq!(read(DuplicateKeys::Collect)?, (vec![Value::I32(1), Value::I32(2)], 1));
+        assert_eq!(read(DuplicateKeys::Error).unwrap_err().to_string(), "bad format -- MAP -- duplicate key /a");
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -3,14 +3,44 @@ extern crate bytes;
 extern crate serde;
 extern crate serde_json;
 extern crate byteorder;
+extern crate sha2;
+#[cfg(feature = "preserve_order")]
+extern crate indexmap;
+#[cfg(feature = "arrow")]
+extern crate arrow_array;
+#[cfg(feature = "arrow")]
+extern crate arrow_buffer;
+#[cfg(feature = "arrow")]
+extern crate arrow_schema;
+
+#[macro_use]
+mod macros;
 
 pub mod deser;
 pub mod ser;
 pub mod spec;
 pub mod gdeser;
+pub mod path;
+mod pointer;
+mod convert;
+pub mod display;
+pub mod total;
+pub mod approx;
+pub mod diff;
+pub mod patch;
+pub mod merge;
+pub mod query;
+pub mod visit;
+pub mod pack;
+pub mod cast;
+pub mod table;
+pub mod pivot;
+pub mod csv;
+#[cfg(feature = "arrow")]
+pub mod arrow;
 
 use std::io::{Cursor, Error};
-use std::collections::HashMap;
+use std::ops::{Index, IndexMut, Range};
 
 use serde::{Serialize, Deserialize};
 
@@ -18,12 +48,59 @@ use std::error;
 use std::fmt;
 
 use deser::{Deserializer, Reader};
-use ser::Serializer;
+pub use deser::{DuplicateKey, DuplicateKeys, Utf8Warning};
+pub use path::{Path, PathSegment};
+pub use total::TotalValue;
+pub use approx::{ApproxMismatch, Tolerance};
+pub use diff::{diff, Change};
+pub use patch::{diff_to_patch, Patch, PatchOp};
+pub use merge::{ListMerge, MergeConflict, MergeOptions};
+pub use query::Query;
+pub use visit::{Visitor, VisitorMut, Walk, WalkMut};
+pub use pack::PackOptions;
+pub use cast::CastMode;
+pub use table::Table;
+pub use pivot::{MixedTypes, PivotOptions, RowView, Rows};
+use ser::{DigestWriter, Serializer};
+use sha2::Sha256;
 use std::convert::TryInto;
 
 use spec::*;
 
-pub static VERSION: &'static str = "1.1.0";
+pub static VERSION: &str = "1.1.0";
+
+/// Map held by `Value::MAP`, keys keep their insertion order with the `preserve_order` feature.
+#[cfg(not(feature = "preserve_order"))]
+pub type Map<K, V> = std::collections::HashMap<K, V>;
+#[cfg(feature = "preserve_order")]
+pub type Map<K, V> = indexmap::IndexMap<K, V>;
+
+/// Removes `key`, also giving its position for `map_insert_at`, which only matters with `preserve_order`.
+#[cfg(not(feature = "preserve_order"))]
+pub(crate) fn map_remove(map: &mut Map<String, Value>, key: &str) -> Option<(usize, Value)> {
+    map.remove(key).map(|value| (0, value))
+}
+#[cfg(feature = "preserve_order")]
+pub(crate) fn map_remove(map: &mut Map<String, Value>, key: &str) -> Option<(usize, Value)> {
+    map.shift_remove_full(key).map(|(index, _, value)| (index, value))
+}
+
+#[cfg(not(feature = "preserve_order"))]
+pub(crate) fn map_insert_at(map: &mut Map<String, Value>, index: usize, key: String, value: Value) {
+    let _ = index;
+    map.insert(key, value);
+}
+#[cfg(feature = "preserve_order")]
+pub(crate) fn map_insert_at(map: &mut Map<String, Value>, index: usize, key: String, value: Value) {
+    map.shift_insert(index, key, value);
+}
+
+/// Keys of either map, sorted so that what is derived from them does not depend on the map order.
+pub(crate) fn sorted_keys<'a>(a: &'a Map<String, Value>, b: &'a Map<String, Value>) -> Vec<&'a String> {
+    let mut keys: Vec<&String> = a.keys().chain(b.keys().filter(|k| !a.contains_key(*k))).collect();
+    keys.sort_unstable();
+    keys
+}
 
 pub type TsonResult<T> = std::result::Result<T, TsonError>;
 
@@ -75,10 +152,9 @@ pub struct StrVec {
 
 impl StrVec {
     pub fn from_bytes(bytes: Vec<u8>) -> TsonResult<Self> {
-        if Self::is_valid_utf8(&bytes)? {
-            Ok(Self::from_bytes_unchecked(bytes))
-        } else {
-            Err(TsonError::new("StrVec -- from_bytes -- not valid utf8"))
+        match Self::find_invalid_utf8(&bytes)? {
+            None => Ok(Self::from_bytes_unchecked(bytes)),
+            Some(index) => Err(TsonError::new(format!("StrVec -- from_bytes -- not valid utf8 -- string {}", index))),
         }
     }
 
@@ -87,38 +163,57 @@ impl StrVec {
     }
 
     pub fn from_bytes_lossy(bytes: Vec<u8>) -> TsonResult<Self> {
-        if Self::is_valid_utf8(&bytes)? {
-            Ok(Self::from_bytes_unchecked(bytes))
-        } else {
-            let mut len_in_bytes = bytes.len();
-            let mut reader = Cursor::new(&bytes);
+        Self::check_terminated(&bytes)?;
+        // NUL is plain ascii, so a replacement never spans two strings and the
+        // whole buffer can be repaired at once.
+        match String::from_utf8(bytes) {
+            Ok(value) => Ok(Self::from_bytes_unchecked(value.into_bytes())),
+            Err(e) => {
+                let value = String::from_utf8_lossy(e.as_bytes()).into_owned();
+                Ok(Self::from_bytes_unchecked(value.into_bytes()))
+            }
+        }
+    }
 
-            let mut start = 0;
-            let mut result = Vec::with_capacity(bytes.len());
+    pub fn is_valid_utf8(bytes: &[u8]) -> TsonResult<bool> {
+        Ok(Self::find_invalid_utf8(bytes)?.is_none())
+    }
 
-            while start < len_in_bytes {
-                let len = read_string_len(&mut reader)?;
-                let value = String::from_utf8_lossy(&bytes[start..(start+len)]);
-                result.extend_from_slice(value.as_bytes());
-                start += len + 1;
+    /// Validates the whole buffer in one pass and returns the index of the first
+    /// string that is not valid utf8, if any.
+    pub fn find_invalid_utf8(bytes: &[u8]) -> TsonResult<Option<usize>> {
+        Self::check_terminated(bytes)?;
+        match core::str::from_utf8(bytes) {
+            Ok(_) => Ok(None),
+            Err(e) => {
+                let index = bytes[..e.valid_up_to()].iter().filter(|b| **b == 0).count();
+                Ok(Some(index))
             }
-            Ok(Self::from_bytes_unchecked(result))
         }
     }
 
-    pub fn is_valid_utf8(bytes: &[u8]) -> TsonResult<bool>  {
-        let mut reader = Cursor::new(bytes);
-        let mut len_in_bytes = bytes.len();
+    /// Index and byte range (terminator excluded) of every string that is not valid utf8.
+    pub fn invalid_utf8_strings(bytes: &[u8]) -> Vec<(usize, Range<usize>)> {
+        let mut result = Vec::new();
         let mut start = 0;
+        let mut index = 0;
+        while let Err(e) = core::str::from_utf8(&bytes[start..]) {
+            let bad = start + e.valid_up_to();
+            index += bytes[start..bad].iter().filter(|b| **b == 0).count();
+            let first = bytes[..bad].iter().rposition(|b| *b == 0).map_or(0, |p| p + 1);
+            let end = bytes[bad..].iter().position(|b| *b == 0).map_or(bytes.len(), |p| bad + p);
+            result.push((index, first..end));
+            index += 1;
+            start = (end + 1).min(bytes.len());
+        }
+        result
+    }
 
-        while start < len_in_bytes {
-            let len = read_string_len(&mut reader)?;
-            if core::str::from_utf8(&bytes[start..(start+len)]).is_err() {
-                return Ok(false);
-            }
-            start += len + 1;
+    fn check_terminated(bytes: &[u8]) -> TsonResult<()> {
+        match bytes.last() {
+            Some(0) | None => Ok(()),
+            Some(_) => Err(TsonError::new("StrVec -- missing string terminator")),
         }
-        Ok(true)
     }
 
     pub fn build_starts(&self) -> TsonResult<Vec<usize>> {
@@ -145,7 +240,7 @@ impl StrVec {
         let mut vec = Vec::new();
         while len_in_bytes > 0 {
             let v = read_string(&mut reader)?;
-            len_in_bytes -= v.as_bytes().len() + 1;
+            len_in_bytes -= v.len() + 1;
             vec.push(v);
         }
         Ok(vec)
@@ -196,7 +291,7 @@ impl TryInto<Vec<String>> for StrVec {
         let mut vec = Vec::new();
         while len_in_bytes > 0 {
             let v = read_string(&mut reader)?;
-            len_in_bytes -= v.as_bytes().len() + 1;
+            len_in_bytes -= v.len() + 1;
             vec.push(v);
         }
         Ok(vec)
@@ -204,11 +299,11 @@ impl TryInto<Vec<String>> for StrVec {
 }
 
 
-impl Into<StrVec> for Vec<String> {
-    fn into(self) -> StrVec {
-        let len_in_bytes = self.iter().map(|e| e.as_bytes().len() + 1).sum();
+impl From<Vec<String>> for StrVec {
+    fn from(value: Vec<String>) -> StrVec {
+        let len_in_bytes = value.iter().map(|e| e.len() + 1).sum();
         let mut bytes = Vec::with_capacity(len_in_bytes);
-        self.iter().for_each(|e| {
+        value.iter().for_each(|e| {
             bytes.extend(e.as_bytes());
             bytes.push(0);
         });
@@ -216,6 +311,24 @@ impl Into<StrVec> for Vec<String> {
     }
 }
 
+macro_rules! typed_list_accessors {
+    ($name:ident, $name_mut:ident, $variant:ident, $t:ty) => {
+        pub fn $name(&self) -> TsonResult<&[$t]> {
+            match *self {
+                Value::$variant(ref v) => Ok(v),
+                _ => Err(self.type_error(TsonType::$variant))
+            }
+        }
+
+        pub fn $name_mut(&mut self) -> TsonResult<&mut Vec<$t>> {
+            match *self {
+                Value::$variant(ref mut v) => Ok(v),
+                _ => Err(self.type_error(TsonType::$variant))
+            }
+        }
+    };
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[serde(untagged)]
 pub enum Value {
@@ -225,7 +338,7 @@ pub enum Value {
     F64(f64),
     BOOL(bool),
     LST(Vec<Value>),
-    MAP(HashMap<String, Value>),
+    MAP(Map<String, Value>),
 
     LSTU8(Vec<u8>),
     LSTI8(Vec<i8>),
@@ -250,21 +363,73 @@ impl Value {
         encode(self)
     }
 
+    /// Sha256 of the canonical encoding, two equal values always have the same digest.
+    pub fn digest(&self) -> TsonResult<Vec<u8>> {
+        let mut writer = DigestWriter::<Sha256>::new();
+        Serializer::canonical().write(self, &mut writer)?;
+        Ok(writer.finalize())
+    }
+
+    pub fn tson_type(&self) -> TsonType {
+        match *self {
+            Value::NULL => TsonType::NULL,
+            Value::STR(_) => TsonType::STR,
+            Value::I32(_) => TsonType::I32,
+            Value::F64(_) => TsonType::F64,
+            Value::BOOL(_) => TsonType::BOOL,
+            Value::LST(_) => TsonType::LST,
+            Value::MAP(_) => TsonType::MAP,
+            Value::LSTU8(_) => TsonType::LSTU8,
+            Value::LSTI8(_) => TsonType::LSTI8,
+            Value::LSTU16(_) => TsonType::LSTU16,
+            Value::LSTI16(_) => TsonType::LSTI16,
+            Value::LSTU32(_) => TsonType::LSTU32,
+            Value::LSTI32(_) => TsonType::LSTI32,
+            Value::LSTU64(_) => TsonType::LSTU64,
+            Value::LSTI64(_) => TsonType::LSTI64,
+            Value::LSTF32(_) => TsonType::LSTF32,
+            Value::LSTF64(_) => TsonType::LSTF64,
+            Value::LSTSTR(_) => TsonType::LSTSTR,
+        }
+    }
+
+    pub(crate) fn type_error(&self, expected: TsonType) -> TsonError {
+        TsonError::new(format!("{} expected -- found {}", expected, self.tson_type()))
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Value::NULL
+    }
+
     pub fn to_str(&self) -> TsonResult<&str> {
         match *self {
             Value::STR(ref v) => {
                 Ok(v.as_str())
             }
-            _ => Err(TsonError::new("str expected"))
+            _ => Err(self.type_error(TsonType::STR))
+        }
+    }
+
+    pub fn to_str_mut(&mut self) -> TsonResult<&mut String> {
+        match *self {
+            Value::STR(ref mut v) => Ok(v),
+            _ => Err(self.type_error(TsonType::STR))
         }
     }
 
-    pub fn to_map(&self) -> TsonResult<&HashMap<String, Value>> {
+    pub fn to_map(&self) -> TsonResult<&Map<String, Value>> {
         match *self {
             Value::MAP(ref v) => {
                 Ok(v)
             }
-            _ => Err(TsonError::new("str expected"))
+            _ => Err(self.type_error(TsonType::MAP))
+        }
+    }
+
+    pub fn to_map_mut(&mut self) -> TsonResult<&mut Map<String, Value>> {
+        match *self {
+            Value::MAP(ref mut v) => Ok(v),
+            _ => Err(self.type_error(TsonType::MAP))
         }
     }
 
@@ -273,7 +438,171 @@ impl Value {
             Value::LST(ref v) => {
                 Ok(v)
             }
-            _ => Err(TsonError::new("lst expected"))
+            _ => Err(self.type_error(TsonType::LST))
+        }
+    }
+
+    pub fn to_list_mut(&mut self) -> TsonResult<&mut Vec<Value>> {
+        match *self {
+            Value::LST(ref mut v) => Ok(v),
+            _ => Err(self.type_error(TsonType::LST))
+        }
+    }
+
+    pub fn as_i32(&self) -> TsonResult<i32> {
+        match *self {
+            Value::I32(v) => Ok(v),
+            _ => Err(self.type_error(TsonType::I32))
+        }
+    }
+
+    pub fn as_i32_mut(&mut self) -> TsonResult<&mut i32> {
+        match *self {
+            Value::I32(ref mut v) => Ok(v),
+            _ => Err(self.type_error(TsonType::I32))
+        }
+    }
+
+    pub fn as_f64(&self) -> TsonResult<f64> {
+        match *self {
+            Value::F64(v) => Ok(v),
+            _ => Err(self.type_error(TsonType::F64))
+        }
+    }
+
+    pub fn as_f64_mut(&mut self) -> TsonResult<&mut f64> {
+        match *self {
+            Value::F64(ref mut v) => Ok(v),
+            _ => Err(self.type_error(TsonType::F64))
+        }
+    }
+
+    pub fn as_bool(&self) -> TsonResult<bool> {
+        match *self {
+            Value::BOOL(v) => Ok(v),
+            _ => Err(self.type_error(TsonType::BOOL))
+        }
+    }
+
+    pub fn as_bool_mut(&mut self) -> TsonResult<&mut bool> {
+        match *self {
+            Value::BOOL(ref mut v) => Ok(v),
+            _ => Err(self.type_error(TsonType::BOOL))
+        }
+    }
+
+    pub fn as_strvec(&self) -> TsonResult<&StrVec> {
+        match *self {
+            Value::LSTSTR(ref v) => Ok(v),
+            _ => Err(self.type_error(TsonType::LSTSTR))
+        }
+    }
+
+    pub fn as_strvec_mut(&mut self) -> TsonResult<&mut StrVec> {
+        match *self {
+            Value::LSTSTR(ref mut v) => Ok(v),
+            _ => Err(self.type_error(TsonType::LSTSTR))
+        }
+    }
+
+    typed_list_accessors!(as_u8_slice, as_u8_slice_mut, LSTU8, u8);
+    typed_list_accessors!(as_i8_slice, as_i8_slice_mut, LSTI8, i8);
+    typed_list_accessors!(as_u16_slice, as_u16_slice_mut, LSTU16, u16);
+    typed_list_accessors!(as_i16_slice, as_i16_slice_mut, LSTI16, i16);
+    typed_list_accessors!(as_u32_slice, as_u32_slice_mut, LSTU32, u32);
+    typed_list_accessors!(as_i32_slice, as_i32_slice_mut, LSTI32, i32);
+    typed_list_accessors!(as_u64_slice, as_u64_slice_mut, LSTU64, u64);
+    typed_list_accessors!(as_i64_slice, as_i64_slice_mut, LSTI64, i64);
+    typed_list_accessors!(as_f32_slice, as_f32_slice_mut, LSTF32, f32);
+    typed_list_accessors!(as_f64_slice, as_f64_slice_mut, LSTF64, f64);
+
+    /// Typed list holding `vec`, the variant being chosen by T.
+    pub fn from_typed<T>(vec: Vec<T>) -> Value where T: TsonTypedListType {
+        T::into_value(vec)
+    }
+
+    /// Elements of a typed list of T, None for any other value.
+    pub fn typed_slice<T>(&self) -> Option<&[T]> where T: TsonTypedListType {
+        T::as_slice(self)
+    }
+
+    pub fn typed_vec_mut<T>(&mut self) -> Option<&mut Vec<T>> where T: TsonTypedListType {
+        T::as_vec_mut(self)
+    }
+
+    /// Value of `key` when self is a MAP holding it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::MAP(ref v) => v.get(key),
+            _ => None
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        match *self {
+            Value::MAP(ref mut v) => v.get_mut(key),
+            _ => None
+        }
+    }
+
+    /// Element `index` when self is a LST long enough.
+    pub fn get_index(&self, index: usize) -> Option<&Value> {
+        match *self {
+            Value::LST(ref v) => v.get(index),
+            _ => None
+        }
+    }
+
+    pub fn get_index_mut(&mut self, index: usize) -> Option<&mut Value> {
+        match *self {
+            Value::LST(ref mut v) => v.get_mut(index),
+            _ => None
+        }
+    }
+}
+
+static NULL: Value = Value::NULL;
+
+/// Like serde_json, missing keys, out of range indices and mismatched types give NULL.
+impl Index<&str> for Value {
+    type Output = Value;
+
+    fn index(&self, key: &str) -> &Value {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+impl Index<usize> for Value {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Value {
+        self.get_index(index).unwrap_or(&NULL)
+    }
+}
+
+/// Inserts NULL for a missing key, a NULL value first becomes an empty MAP.
+/// Panics if self is neither a MAP nor NULL.
+impl IndexMut<&str> for Value {
+    fn index_mut(&mut self, key: &str) -> &mut Value {
+        if self.is_null() {
+            *self = Value::MAP(Map::new());
+        }
+        match *self {
+            Value::MAP(ref mut v) => v.entry(key.to_owned()).or_insert(Value::NULL),
+            _ => panic!("cannot index {} with key {:?}", self.tson_type(), key),
+        }
+    }
+}
+
+/// Panics if self is not a LST or if index is out of range.
+impl IndexMut<usize> for Value {
+    fn index_mut(&mut self, index: usize) -> &mut Value {
+        match *self {
+            Value::LST(ref mut v) => {
+                let len = v.len();
+                v.get_mut(index).unwrap_or_else(|| panic!("index {} out of range for LST of length {}", index, len))
+            }
+            _ => panic!("cannot index {} with {}", self.tson_type(), index),
         }
     }
 }
@@ -291,6 +620,12 @@ pub fn encode(value: &Value) -> TsonResult<Vec<u8>> {
     ser.encode(value)
 }
 
+/// Encodes with sorted map keys and normalised floats, see `Serializer::canonical`.
+pub fn encode_canonical(value: &Value) -> TsonResult<Vec<u8>> {
+    let ser = Serializer::canonical();
+    ser.encode(value)
+}
+
 pub fn decode(mut cur: Cursor<&[u8]>) -> TsonResult<Value> {
     let deser = Deserializer::new(false);
     deser.read(&mut cur)
@@ -329,24 +664,25 @@ mod tests {
 
     #[test]
     fn lst() {
-        let mut vec = Vec::new();
-        vec.push(Value::NULL);
-        vec.push(Value::BOOL(true));
-        vec.push(Value::I32(42));
-        vec.push(Value::F64(42.0));
-        vec.push(Value::STR("42.0".to_owned()));
-        vec.push(Value::STR("".to_owned()));
-        vec.push(Value::LSTU8(vec![42]));
-        vec.push(Value::LSTI8(vec![42]));
-        vec.push(Value::LSTU16(vec![42]));
-        vec.push(Value::LSTI16(vec![42]));
-        vec.push(Value::LSTU32(vec![42]));
-        vec.push(Value::LSTI32(vec![42]));
-        vec.push(Value::LSTU64(vec![42]));
-        vec.push(Value::LSTI64(vec![42]));
-        vec.push(Value::LSTF32(vec![42.0]));
-        vec.push(Value::LSTF64(vec![42.0]));
-        vec.push(Value::LSTSTR(vec!["42".to_owned()].into()));
+        let vec = vec![
+            Value::NULL,
+            Value::BOOL(true),
+            Value::I32(42),
+            Value::F64(42.0),
+            Value::STR("42.0".to_owned()),
+            Value::STR("".to_owned()),
+            Value::LSTU8(vec![42]),
+            Value::LSTI8(vec![42]),
+            Value::LSTU16(vec![42]),
+            Value::LSTI16(vec![42]),
+            Value::LSTU32(vec![42]),
+            Value::LSTI32(vec![42]),
+            Value::LSTU64(vec![42]),
+            Value::LSTI64(vec![42]),
+            Value::LSTF32(vec![42.0]),
+            Value::LSTF64(vec![42.0]),
+            Value::LSTSTR(vec!["42".to_owned()].into()),
+        ];
 
 
         let object = Value::LST(vec);
@@ -359,18 +695,17 @@ mod tests {
 
         let data = r#"[null,true,42,42.0,"42.0",[42],[42],[42],[42],[42],[42],[42],[42],[42.0],[42.0],["42"]]"#;
 
-        let p = decode_json(data.to_string().as_bytes()).unwrap();
+        let _p = decode_json(data.to_string().as_bytes()).unwrap();
 
         // println!("{:#?}", p);
 
-        let ser = Serializer::new();
+        let _ser = Serializer::new();
         // println!("p encoded_size {:#?}", ser.encoded_size(&p));
     }
 
     #[test]
     fn empty_string() {
-        let mut vec = Vec::new();
-        vec.push(Value::STR("".to_owned()));
+        let vec = vec![Value::STR("".to_owned())];
         let object = Value::LST(vec);
         let bytes = encode(&object).unwrap();
         println!("{:#?}", bytes);
@@ -379,10 +714,10 @@ mod tests {
 
     #[test]
     fn map() {
-        let mut map = HashMap::new();
+        let mut map = Map::new();
         map.insert("i42".to_owned(), Value::I32(42));
 
-        let mut inner_map = HashMap::new();
+        let mut inner_map = Map::new();
         inner_map.insert("u42".to_owned(), Value::LSTU8(vec![42]));
 
         map.insert("map".to_owned(), Value::MAP(inner_map));
@@ -390,6 +725,258 @@ mod tests {
         encode_decode(&Value::MAP(map))
     }
 
+    #[test]
+    fn str_vec_invalid_utf8() {
+        let mut bytes = b"a\0bc\0".to_vec();
+        bytes.extend_from_slice(&[0xff, b'd', 0]);
+        bytes.extend_from_slice(b"e\0");
+
+        assert_eq!(StrVec::find_invalid_utf8(&bytes).unwrap(), Some(2));
+        assert!(!StrVec::is_valid_utf8(&bytes).unwrap());
+        assert!(StrVec::from_bytes(bytes.clone()).unwrap_err().to_string().ends_with("string 2"));
+
+        let lossy: Vec<String> = StrVec::from_bytes_lossy(bytes).unwrap().try_into().unwrap();
+        assert_eq!(lossy, vec!["a", "bc", "\u{FFFD}d", "e"]);
+    }
+
+    #[test]
+    fn str_vec_missing_terminator() {
+        assert!(StrVec::from_bytes(b"a\0b".to_vec()).is_err());
+        assert!(StrVec::from_bytes_lossy(b"a\0b".to_vec()).is_err());
+        assert!(StrVec::from_bytes(Vec::new()).is_ok());
+    }
+
+    fn corrupt(value: &Value) -> Vec<u8> {
+        let mut bytes = encode(value).unwrap();
+        bytes.iter_mut().filter(|b| **b == b'#').for_each(|b| *b = 0xff);
+        bytes
+    }
+
+    #[test]
+    fn lossy_warnings() {
+        let mut map = Map::new();
+        map.insert("name".to_owned(), Value::LST(vec![Value::NULL, Value::STR("a#".to_owned())]));
+        map.insert("names".to_owned(), Value::LSTSTR(vec!["ok".to_owned(), "#b".to_owned()].into()));
+        let bytes = corrupt(&Value::MAP(map));
+
+        assert!(Deserializer::new(false).read(&mut Cursor::new(&bytes)).is_err());
+
+        let deser = Deserializer::new(true).collect_warnings(true);
+        let value = deser.read(&mut Cursor::new(&bytes)).unwrap();
+        let mut warnings = deser.take_warnings();
+        warnings.sort_by_key(|w| w.path.to_string());
+        assert_eq!(deser.offset(), bytes.len());
+        assert_eq!(warnings.len(), 2);
+
+        assert_eq!(warnings[0].path.to_string(), "/name/1");
+        assert_eq!(warnings[0].bytes, vec![b'a', 0xff]);
+        assert_eq!(warnings[1].path, Path::new().key("names").index(1));
+        assert_eq!(warnings[1].bytes, vec![0xff, b'b']);
+        for w in warnings.iter() {
+            assert_eq!(&bytes[w.offset..w.offset + w.bytes.len()], &w.bytes[..]);
+        }
+
+        let map = value.to_map().unwrap();
+        assert_eq!(map["name"], Value::LST(vec![Value::NULL, Value::STR("a\u{FFFD}".to_owned())]));
+
+        // A reused deserializer starts over, even after a failed read.
+        assert!(deser.read(&mut Cursor::new(&bytes[..bytes.len() - 3])).is_err());
+        deser.read(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(deser.offset(), bytes.len());
+        let mut again = deser.take_warnings();
+        again.sort_by_key(|w| w.path.to_string());
+        assert_eq!(again, warnings);
+    }
+
+    fn duplicated_map() -> Vec<u8> {
+        let mut bytes = vec![STRING_TYPE];
+        bytes.extend_from_slice(VERSION.as_bytes());
+        bytes.extend_from_slice(&[0, MAP_TYPE, 2, 0, 0, 0]);
+        for i in 1..3 {
+            bytes.extend_from_slice(&[STRING_TYPE, b'a', 0, INTEGER_TYPE, i, 0, 0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let bytes = duplicated_map();
+        let read = |policy| {
+            let deser = Deserializer::new(false).duplicate_keys(policy);
+            deser.read(&mut Cursor::new(&bytes)).map(|v| (v.to_map().unwrap()["a"].clone(), deser.take_duplicates()))
+        };
+
+        assert_eq!(read(DuplicateKeys::LastWins).unwrap(), (Value::I32(2), vec![]));
+        assert_eq!(read(DuplicateKeys::FirstWins).unwrap(), (Value::I32(1), vec![]));
+        assert_eq!(read(DuplicateKeys::Error).unwrap_err().to_string(), "wrong format -- MAP -- duplicate key /a");
+
+        let duplicate = DuplicateKey { path: Path::new().key("a"), offset: bytes.len() - 7 };
+        assert_eq!(read(DuplicateKeys::Collect).unwrap(), (Value::I32(2), vec![duplicate]));
+    }
+
+    #[cfg(feature = "preserve_order")]
+    #[test]
+    fn preserve_order() {
+        let keys = vec!["z", "a", "m", "b", "y"];
+        let mut map = Map::new();
+        for (i, key) in keys.iter().enumerate() {
+            map.insert(key.to_string(), Value::I32(i as i32));
+        }
+        let object = Value::MAP(map);
+
+        let decoded = decode_bytes(&encode(&object).unwrap()).unwrap();
+        let decoded_keys: Vec<&str> = decoded.to_map().unwrap().keys().map(|k| k.as_str()).collect();
+        assert_eq!(decoded_keys, keys);
+
+        let json = encode_json(&object).unwrap();
+        assert_eq!(json, r#"{"z":0,"a":1,"m":2,"b":3,"y":4}"#);
+        assert_eq!(decode_json(json.as_bytes()).unwrap().to_map().unwrap().keys().collect::<Vec<_>>(), keys);
+    }
+
+    fn shuffled_map(keys: &[&str]) -> Value {
+        let mut map = Map::new();
+        for key in keys.iter() {
+            map.insert(key.to_string(), Value::LST(vec![Value::STR(key.to_string()), Value::F64(-0.0)]));
+        }
+        Value::MAP(map)
+    }
+
+    #[test]
+    fn canonical_encoding() {
+        let keys: Vec<String> = (0..64).map(|i| format!("key{}", (i * 37) % 64)).collect();
+        let keys: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+        let mut reversed = keys.clone();
+        reversed.reverse();
+
+        let bytes = encode_canonical(&shuffled_map(&keys)).unwrap();
+        for _ in 0..10 {
+            assert_eq!(encode_canonical(&shuffled_map(&keys)).unwrap(), bytes);
+            assert_eq!(encode_canonical(&shuffled_map(&reversed)).unwrap(), bytes);
+        }
+        assert_eq!(decode_bytes(&bytes).unwrap(), shuffled_map(&keys));
+    }
+
+    #[test]
+    fn canonical_floats() {
+        let other_nan = f64::from_bits(f64::NAN.to_bits() | 1);
+        let a = Value::LST(vec![Value::F64(-0.0), Value::LSTF64(vec![f64::NAN, -0.0]), Value::LSTF32(vec![-f32::NAN])]);
+        let b = Value::LST(vec![Value::F64(0.0), Value::LSTF64(vec![other_nan, 0.0]), Value::LSTF32(vec![f32::NAN])]);
+
+        assert_ne!(encode(&a).unwrap(), encode(&b).unwrap());
+        assert_eq!(encode_canonical(&a).unwrap(), encode_canonical(&b).unwrap());
+        assert_eq!(a.digest().unwrap(), b.digest().unwrap());
+        assert_eq!(a.digest().unwrap().len(), 32);
+        assert_ne!(a.digest().unwrap(), Value::F64(1.0).digest().unwrap());
+    }
+
+    #[test]
+    fn accessors() {
+        let mut map = Map::new();
+        map.insert("i".to_owned(), Value::I32(42));
+        map.insert("values".to_owned(), Value::LSTF64(vec![1.0, 2.0]));
+        map.insert("list".to_owned(), Value::LST(vec![Value::BOOL(true), Value::NULL]));
+        let mut object = Value::MAP(map);
+
+        assert_eq!(object.get("i").unwrap().as_i32(), Ok(42));
+        assert_eq!(object.get("values").unwrap().as_f64_slice(), Ok(&[1.0, 2.0][..]));
+        assert_eq!(object.get("list").unwrap().get_index(0).unwrap().as_bool(), Ok(true));
+        assert!(object.get("list").unwrap().get_index(1).unwrap().is_null());
+        assert!(object.get("list").unwrap().get_index(2).is_none());
+        assert!(object.get("missing").is_none());
+
+        let error = object.get("values").unwrap().as_i32_slice().unwrap_err();
+        assert_eq!(error.to_string(), "LSTI32 expected -- found LSTF64");
+        assert_eq!(Value::NULL.to_map().unwrap_err().to_string(), "MAP expected -- found NULL");
+
+        object.get_mut("values").unwrap().as_f64_slice_mut().unwrap().push(3.0);
+        *object.get_mut("i").unwrap().as_i32_mut().unwrap() += 1;
+        *object.get_mut("list").unwrap().get_index_mut(1).unwrap() = Value::F64(0.5);
+
+        assert_eq!(object.get("values").unwrap().as_f64_slice().unwrap().len(), 3);
+        assert_eq!(object.get("i").unwrap().as_i32(), Ok(43));
+        assert_eq!(object.get("list").unwrap().get_index(1).unwrap().as_f64(), Ok(0.5));
+        assert!(Value::LSTSTR(vec!["a".to_owned()].into()).as_strvec().is_ok());
+    }
+
+    #[test]
+    fn index() {
+        let mut object = tson!({"list": [1, {"a": f64[1.0]}], "i": 42});
+
+        assert_eq!(object["i"], Value::I32(42));
+        assert_eq!(object["list"][1]["a"], Value::LSTF64(vec![1.0]));
+        assert_eq!(object["missing"][3]["key"], Value::NULL);
+        assert_eq!(object["i"]["key"], Value::NULL);
+
+        object["list"][0] = Value::STR("one".to_owned());
+        object["new"]["nested"] = Value::BOOL(true);
+        assert_eq!(object["list"][0], Value::STR("one".to_owned()));
+        assert_eq!(object["new"], tson!({"nested": true}));
+    }
+
+    #[test]
+    fn tson_macro() {
+        let mut map = Map::new();
+        map.insert("name".to_owned(), Value::STR("factor1".to_owned()));
+        map.insert("values".to_owned(), Value::LST(vec![
+            Value::NULL,
+            Value::BOOL(false),
+            Value::I32(-1),
+            Value::F64(2.5),
+            Value::LST(vec![]),
+            Value::MAP(Map::new()),
+        ]));
+        map.insert("typed".to_owned(), Value::LST(vec![
+            Value::LSTU8(vec![1, 2]),
+            Value::LSTI8(vec![-1]),
+            Value::LSTU16(vec![1]),
+            Value::LSTI16(vec![-1]),
+            Value::LSTU32(vec![1]),
+            Value::LSTI32(vec![-1, 2]),
+            Value::LSTU64(vec![1]),
+            Value::LSTI64(vec![-1]),
+            Value::LSTF32(vec![1.5]),
+            Value::LSTF64(vec![]),
+            Value::LSTSTR(vec!["a".to_owned(), "b".to_owned()].into()),
+        ]));
+        let count = 3;
+        map.insert("count".to_owned(), Value::I32(count));
+
+        let object = tson!({
+            "name": "factor1",
+            "values": [null, false, -1, 2.5, [], {}],
+            "typed": [u8[1, 2], i8[-1], u16[1], i16[-1], u32[1], i32[-1, 2], u64[1], i64[-1], f32[1.5], f64[], str["a", "b"]],
+            "count": count,
+        });
+        assert_eq!(object, Value::MAP(map));
+        assert_eq!(tson!([1, 2, 3]), Value::LST(vec![Value::I32(1), Value::I32(2), Value::I32(3)]));
+        assert_eq!(tson!(null), Value::NULL);
+    }
+
+    #[test]
+    fn typed() {
+        fn sum<T>(value: &Value) -> f64 where T: TsonTypedListType + Into<f64> {
+            value.typed_slice::<T>().unwrap().iter().map(|v| (*v).into()).sum()
+        }
+
+        let values = Value::from_typed(vec![1u16, 2, 3]);
+        assert_eq!(values, Value::LSTU16(vec![1, 2, 3]));
+        assert_eq!(sum::<u16>(&values), 6.0);
+        assert_eq!(sum::<f32>(&Value::from_typed(vec![0.5f32, 0.5])), 1.0);
+        assert!(values.typed_slice::<i16>().is_none());
+
+        let mut values = values;
+        values.typed_vec_mut::<u16>().unwrap().push(4);
+        assert_eq!(values.typed_slice::<u16>(), Some(&[1, 2, 3, 4][..]));
+
+        let bytes = encode(&Value::LSTI64(vec![-1, 2])).unwrap();
+        let deser = Deserializer::new(false);
+        let mut reader = Cursor::new(&bytes[1 + VERSION.len() + 1..]);
+        assert_eq!(deser.read_typed_list::<i64>(&mut reader).unwrap(), vec![-1, 2]);
+
+        let mut reader = Cursor::new(&bytes[1 + VERSION.len() + 1..]);
+        assert!(deser.read_typed_list::<u64>(&mut reader).is_err());
+    }
+
 //    #[bench]
 //    fn bench(b: &mut Bencher) {
 //        let mut f = File::open("../dtson/bin/test_data.tson").expect("file not found");
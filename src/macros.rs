@@ -0,0 +1,78 @@
+/// Builds a `Value` from a JSON like literal.
+///
+/// Integers give `I32`, floats `F64`, and typed lists are written with their
+/// element type in front of the brackets, `str` giving a `LSTSTR` :
+///
+/// ```
+/// # #[macro_use] extern crate rustson;
+/// # fn main() {
+/// let value = tson!({
+///     "name": "factor1",
+///     "count": 2,
+///     "values": f64[0.0, 42.0],
+///     "levels": str["a", "b"],
+///     "tags": [null, true, -1, {"nested": u8[1, 2]}]
+/// });
+/// assert_eq!(value["values"], rustson::Value::LSTF64(vec![0.0, 42.0]));
+/// # }
+/// ```
+///
+/// Nested values expand through `$crate`, so the macro can also be called by path :
+///
+/// ```
+/// let value = rustson::tson!({"values": [1, {"a": f64[1.0]}]});
+/// assert_eq!(value["values"][1]["a"], rustson::Value::LSTF64(vec![1.0]));
+/// ```
+#[macro_export]
+macro_rules! tson {
+    (null) => { $crate::Value::NULL };
+
+    (u8 [ $($e:expr),* $(,)* ]) => { $crate::Value::LSTU8(vec![$($e),*]) };
+    (i8 [ $($e:expr),* $(,)* ]) => { $crate::Value::LSTI8(vec![$($e),*]) };
+    (u16 [ $($e:expr),* $(,)* ]) => { $crate::Value::LSTU16(vec![$($e),*]) };
+    (i16 [ $($e:expr),* $(,)* ]) => { $crate::Value::LSTI16(vec![$($e),*]) };
+    (u32 [ $($e:expr),* $(,)* ]) => { $crate::Value::LSTU32(vec![$($e),*]) };
+    (i32 [ $($e:expr),* $(,)* ]) => { $crate::Value::LSTI32(vec![$($e),*]) };
+    (u64 [ $($e:expr),* $(,)* ]) => { $crate::Value::LSTU64(vec![$($e),*]) };
+    (i64 [ $($e:expr),* $(,)* ]) => { $crate::Value::LSTI64(vec![$($e),*]) };
+    (f32 [ $($e:expr),* $(,)* ]) => { $crate::Value::LSTF32(vec![$($e),*]) };
+    (f64 [ $($e:expr),* $(,)* ]) => { $crate::Value::LSTF64(vec![$($e),*]) };
+    (str [ $($e:expr),* $(,)* ]) => {
+        $crate::Value::LSTSTR(vec![$(::std::string::String::from($e)),*].into())
+    };
+
+    // Elements made of a single token do not need to be munched.
+    ([ $($e:tt),* $(,)* ]) => { $crate::Value::LST(vec![$($crate::tson!($e)),*]) };
+    ([ $($tt:tt)* ]) => { $crate::Value::LST($crate::tson!(@list [] [] $($tt)*)) };
+
+    ({}) => { $crate::Value::MAP($crate::Map::new()) };
+    ({ $($tt:tt)+ }) => {{
+        let mut map = $crate::Map::new();
+        $crate::tson!(@map map $($tt)*);
+        $crate::Value::MAP(map)
+    }};
+
+    (@list [$($done:expr,)*] [$($cur:tt)+] , $($rest:tt)*) => {
+        $crate::tson!(@list [$($done,)* $crate::tson!($($cur)+),] [] $($rest)*)
+    };
+    (@list [$($done:expr,)*] [$($cur:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::tson!(@list [$($done,)*] [$($cur)* $next] $($rest)*)
+    };
+    (@list [$($done:expr,)*] [$($cur:tt)+]) => { vec![$($done,)* $crate::tson!($($cur)+)] };
+    (@list [$($done:expr,)*] []) => { vec![$($done),*] };
+
+    (@map $map:ident) => {};
+    (@map $map:ident $key:tt : $($rest:tt)*) => { $crate::tson!(@entry $map $key [] $($rest)*); };
+    (@entry $map:ident $key:tt [$($cur:tt)+] , $($rest:tt)*) => {
+        $map.insert(::std::string::String::from($key), $crate::tson!($($cur)+));
+        $crate::tson!(@map $map $($rest)*);
+    };
+    (@entry $map:ident $key:tt [$($cur:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::tson!(@entry $map $key [$($cur)* $next] $($rest)*);
+    };
+    (@entry $map:ident $key:tt [$($cur:tt)+]) => {
+        $map.insert(::std::string::String::from($key), $crate::tson!($($cur)+));
+    };
+
+    ($other:expr) => { $crate::Value::from($other) };
+}
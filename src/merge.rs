@@ -0,0 +1,237 @@
+use std::fmt;
+
+use ::{map_remove, Path, PathSegment, StrVec, Value};
+use spec::{TsonType, TsonTypedListType};
+
+/// How two lists of the same type are merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListMerge {
+    /// The other list replaces the list.
+    Replace,
+    /// The other list is appended to the list.
+    Append,
+    /// Elements are merged by index, the longer list giving the remaining elements.
+    ElementWise,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergeOptions {
+    /// Strategy for LST values, element-wise merging them recursively.
+    pub lists: ListMerge,
+    /// Strategy for typed lists and LSTSTR, element-wise taking the other element.
+    pub typed_lists: ListMerge,
+    /// A NULL in a map of the other value removes the key instead of being merged.
+    pub null_deletes: bool,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions { lists: ListMerge::Replace, typed_lists: ListMerge::Replace, null_deletes: false }
+    }
+}
+
+/// Values of different types found at the same path, the other value having replaced the value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeConflict {
+    pub path: Path,
+    pub from: TsonType,
+    pub to: TsonType,
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = self.path.to_display_string();
+        write!(f, "{} -- type conflict -- {} replaced by {}", path, self.from, self.to)
+    }
+}
+
+impl Value {
+    /// Merges `other` into self, maps being merged recursively and other values replaced.
+    ///
+    /// NULL never conflicts: it is replaced, or replaces unless it deletes a map entry.
+    pub fn merge(&mut self, other: Value, options: MergeOptions) -> Vec<MergeConflict> {
+        let mut conflicts = Vec::new();
+        merge(self, other, &options, &mut Path::new(), &mut conflicts);
+        conflicts
+    }
+}
+
+fn merge(value: &mut Value, other: Value, options: &MergeOptions, path: &mut Path, conflicts: &mut Vec<MergeConflict>) {
+    if value.tson_type() != other.tson_type() {
+        if !value.is_null() && !other.is_null() {
+            conflicts.push(MergeConflict { path: path.clone(), from: value.tson_type(), to: other.tson_type() });
+        }
+        *value = other;
+        if options.null_deletes {
+            remove_nulls(value);
+        }
+        return;
+    }
+    match (value, other) {
+        (&mut Value::MAP(ref mut map), Value::MAP(other)) => {
+            // In key order so that conflicts do not depend on the map order.
+            let mut entries: Vec<(String, Value)> = other.into_iter().collect();
+            entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+            for (key, other) in entries {
+                if other.is_null() && options.null_deletes {
+                    map_remove(map, &key);
+                    continue;
+                }
+                path.push(PathSegment::Key(key.clone()));
+                match map.get_mut(&key) {
+                    Some(value) => merge(value, other, options, path, conflicts),
+                    None => {
+                        let mut value = Value::NULL;
+                        merge(&mut value, other, options, path, conflicts);
+                        map.insert(key, value);
+                    }
+                }
+                path.pop();
+            }
+        }
+        (&mut Value::LST(ref mut list), Value::LST(other)) => {
+            let start = match options.lists {
+                ListMerge::Replace => {
+                    *list = other;
+                    0
+                }
+                ListMerge::Append => {
+                    let start = list.len();
+                    list.extend(other);
+                    start
+                }
+                ListMerge::ElementWise => {
+                    let start = list.len();
+                    for (i, other) in other.into_iter().enumerate() {
+                        if i < start {
+                            path.push(PathSegment::Index(i));
+                            merge(&mut list[i], other, options, path, conflicts);
+                            path.pop();
+                        } else {
+                            list.push(other);
+                        }
+                    }
+                    start
+                }
+            };
+            if options.null_deletes {
+                list[start..].iter_mut().for_each(remove_nulls);
+            }
+        }
+        (&mut Value::LSTU8(ref mut list), Value::LSTU8(other)) => merge_typed(list, other, options.typed_lists),
+        (&mut Value::LSTI8(ref mut list), Value::LSTI8(other)) => merge_typed(list, other, options.typed_lists),
+        (&mut Value::LSTU16(ref mut list), Value::LSTU16(other)) => merge_typed(list, other, options.typed_lists),
+        (&mut Value::LSTI16(ref mut list), Value::LSTI16(other)) => merge_typed(list, other, options.typed_lists),
+        (&mut Value::LSTU32(ref mut list), Value::LSTU32(other)) => merge_typed(list, other, options.typed_lists),
+        (&mut Value::LSTI32(ref mut list), Value::LSTI32(other)) => merge_typed(list, other, options.typed_lists),
+        (&mut Value::LSTU64(ref mut list), Value::LSTU64(other)) => merge_typed(list, other, options.typed_lists),
+        (&mut Value::LSTI64(ref mut list), Value::LSTI64(other)) => merge_typed(list, other, options.typed_lists),
+        (&mut Value::LSTF32(ref mut list), Value::LSTF32(other)) => merge_typed(list, other, options.typed_lists),
+        (&mut Value::LSTF64(ref mut list), Value::LSTF64(other)) => merge_typed(list, other, options.typed_lists),
+        (&mut Value::LSTSTR(ref mut strings), Value::LSTSTR(other)) => merge_str_vec(strings, other, options.typed_lists),
+        (value, other) => *value = other,
+    }
+}
+
+/// Removes the NULL map entries of a value of the other value taken as a whole.
+fn remove_nulls(value: &mut Value) {
+    match *value {
+        Value::MAP(ref mut map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        Value::LST(ref mut list) => list.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+fn merge_typed<T>(list: &mut Vec<T>, other: Vec<T>, strategy: ListMerge) where T: TsonTypedListType {
+    match strategy {
+        ListMerge::Replace => *list = other,
+        ListMerge::Append => list.extend(other),
+        ListMerge::ElementWise => {
+            let common = list.len().min(other.len());
+            list[..common].copy_from_slice(&other[..common]);
+            list.extend_from_slice(&other[common..]);
+        }
+    }
+}
+
+fn merge_str_vec(strings: &mut StrVec, other: StrVec, strategy: ListMerge) {
+    match strategy {
+        ListMerge::Replace => *strings = other,
+        ListMerge::Append => strings.bytes.extend(other.bytes),
+        ListMerge::ElementWise => {
+            // The other strings followed by the strings of self beyond their count.
+            let count = other.bytes.iter().filter(|b| **b == 0).count();
+            let tail = match count {
+                0 => 0,
+                _ => strings.bytes.iter().enumerate().filter(|&(_, b)| *b == 0).nth(count - 1)
+                    .map_or(strings.bytes.len(), |(i, _)| i + 1),
+            };
+            let mut bytes = other.bytes;
+            bytes.extend_from_slice(&strings.bytes[tail..]);
+            strings.bytes = bytes;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use merge::{ListMerge, MergeOptions};
+
+    #[test]
+    fn merge_maps() {
+        let mut value = tson!({"a": 1, "b": {"c": "x", "d": [1, 2]}, "e": true, "f": f64[1.0]});
+        let conflicts = value.merge(tson!({"a": 2, "b": {"d": [3], "g": null}, "e": null, "f": "x"}), MergeOptions::default());
+        assert_eq!(value, tson!({"a": 2, "b": {"c": "x", "d": [3], "g": null}, "e": null, "f": "x"}));
+        assert_eq!(conflicts.iter().map(|c| c.to_string()).collect::<Vec<_>>(), vec!["/f -- type conflict -- LSTF64 replaced by STR"]);
+
+        let options = MergeOptions { null_deletes: true, ..MergeOptions::default() };
+        let mut value = tson!({"a": 1, "b": {"c": 2}});
+        let conflicts = value.merge(tson!({"a": null, "b": {"c": null, "d": 3}, "e": {"f": null, "g": 1}}), options);
+        assert_eq!(value, tson!({"b": {"d": 3}, "e": {"g": 1}}));
+        assert!(conflicts.is_empty());
+
+        let mut value = tson!({"b": 1, "a": 1, "c": {"d": 1}});
+        let conflicts = value.merge(tson!({"c": {"d": "x"}, "b": "x", "a": "x"}), MergeOptions::default());
+        let paths: Vec<String> = conflicts.iter().map(|c| c.path.to_string()).collect();
+        assert_eq!(paths, vec!["/a", "/b", "/c/d"]);
+    }
+
+    #[test]
+    fn merge_lists() {
+        let value = tson!({"l": [1, {"a": 1}], "v": f64[1.0, 2.0, 3.0], "s": str["a", "b", "c"]});
+        let other = tson!({"l": [2, {"b": 2}, 3], "v": f64[4.0], "s": str["x"]});
+
+        let options = |lists, typed_lists| MergeOptions { lists, typed_lists, null_deletes: false };
+
+        let mut merged = value.clone();
+        merged.merge(other.clone(), options(ListMerge::Append, ListMerge::Append));
+        assert_eq!(merged, tson!({"l": [1, {"a": 1}, 2, {"b": 2}, 3], "v": f64[1.0, 2.0, 3.0, 4.0], "s": str["a", "b", "c", "x"]}));
+
+        let mut merged = value.clone();
+        merged.merge(other.clone(), options(ListMerge::ElementWise, ListMerge::ElementWise));
+        assert_eq!(merged, tson!({"l": [2, {"a": 1, "b": 2}, 3], "v": f64[4.0, 2.0, 3.0], "s": str["x", "b", "c"]}));
+
+        let mut merged = value;
+        merged.merge(other.clone(), options(ListMerge::Replace, ListMerge::Replace));
+        assert_eq!(merged, other);
+
+        let other = tson!({"l": [{"a": null, "b": [{"c": null}]}, null]});
+        let deleting = |lists| MergeOptions { lists, typed_lists: ListMerge::Replace, null_deletes: true };
+        let mut merged = tson!({"l": [1]});
+        merged.merge(other.clone(), deleting(ListMerge::Append));
+        assert_eq!(merged, tson!({"l": [1, {"b": [{}]}, null]}));
+        let mut merged = tson!({"l": [{"a": 1}]});
+        merged.merge(other.clone(), deleting(ListMerge::ElementWise));
+        assert_eq!(merged, tson!({"l": [{"b": [{}]}, null]}));
+        let mut merged = tson!({});
+        merged.merge(other, deleting(ListMerge::Replace));
+        assert_eq!(merged, tson!({"l": [{"b": [{}]}, null]}));
+
+        let mut strings = tson!(str["a"]);
+        strings.merge(tson!(str["x", "y"]), options(ListMerge::Replace, ListMerge::ElementWise));
+        assert_eq!(strings, tson!(str["x", "y"]));
+    }
+}
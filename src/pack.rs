@@ -0,0 +1,121 @@
+use ::{Map, StrVec, TsonResult, Value};
+use pointer::int_value;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PackOptions {
+    /// Integers are packed into the narrowest of LSTU8, LSTI8, LSTU16, LSTI16 and LSTI32 holding them.
+    pub narrow_integers: bool,
+}
+
+impl Value {
+    /// Converts, recursively, the non empty LST of I32 into LSTI32, of I32 and F64 into LSTF64
+    /// and of STR into LSTSTR, other values being kept as is.
+    ///
+    /// Strings holding a nul byte cannot be packed.
+    pub fn pack(&self, options: PackOptions) -> Value {
+        match *self {
+            Value::LST(ref list) => pack_list(list, options),
+            Value::MAP(ref map) => Value::MAP(map.iter().map(|(k, v)| (k.clone(), v.pack(options))).collect::<Map<_, _>>()),
+            _ => self.clone(),
+        }
+    }
+
+    /// Converts, recursively, typed lists into LST of I32, F64 and STR, the inverse of `pack`.
+    ///
+    /// Fails on 64 bits integers not held exactly by an F64, and on LSTSTR not holding utf8.
+    pub fn unpack(&self) -> TsonResult<Value> {
+        fn ints<T>(list: &[T]) -> TsonResult<Value> where T: Into<i128> + Copy {
+            list.iter().map(|e| int_value(*e)).collect::<TsonResult<Vec<_>>>().map(Value::LST)
+        }
+        match *self {
+            Value::LST(ref list) => list.iter().map(|e| e.unpack()).collect::<TsonResult<Vec<_>>>().map(Value::LST),
+            Value::MAP(ref map) => map.iter()
+                .map(|(k, v)| v.unpack().map(|v| (k.clone(), v)))
+                .collect::<TsonResult<Map<_, _>>>()
+                .map(Value::MAP),
+            Value::LSTU8(ref list) => ints(list),
+            Value::LSTI8(ref list) => ints(list),
+            Value::LSTU16(ref list) => ints(list),
+            Value::LSTI16(ref list) => ints(list),
+            Value::LSTU32(ref list) => ints(list),
+            Value::LSTI32(ref list) => Ok(Value::LST(list.iter().map(|e| Value::I32(*e)).collect())),
+            Value::LSTU64(ref list) => ints(list),
+            Value::LSTI64(ref list) => ints(list),
+            Value::LSTF32(ref list) => Ok(Value::LST(list.iter().map(|e| Value::F64(f64::from(*e))).collect())),
+            Value::LSTF64(ref list) => Ok(Value::LST(list.iter().map(|e| Value::F64(*e)).collect())),
+            Value::LSTSTR(ref strings) => Ok(Value::LST(strings.try_to_vec()?.into_iter().map(Value::STR).collect())),
+            _ => Ok(self.clone()),
+        }
+    }
+}
+
+fn pack_list(list: &[Value], options: PackOptions) -> Value {
+    if list.is_empty() {
+        return Value::LST(Vec::new());
+    }
+    if list.iter().all(|e| matches!(*e, Value::STR(ref s) if !s.contains('\0'))) {
+        let strings: Vec<String> = list.iter().filter_map(|e| match *e {
+            Value::STR(ref s) => Some(s.clone()),
+            _ => None,
+        }).collect();
+        return Value::LSTSTR(StrVec::from(strings));
+    }
+    if list.iter().all(|e| matches!(*e, Value::I32(_))) {
+        let ints: Vec<i32> = list.iter().filter_map(|e| e.as_i32().ok()).collect();
+        return if options.narrow_integers { narrow(ints) } else { Value::LSTI32(ints) };
+    }
+    if list.iter().all(|e| matches!(*e, Value::I32(_) | Value::F64(_))) {
+        return Value::LSTF64(list.iter().filter_map(|e| match *e {
+            Value::I32(v) => Some(f64::from(v)),
+            Value::F64(v) => Some(v),
+            _ => None,
+        }).collect());
+    }
+    Value::LST(list.iter().map(|e| e.pack(options)).collect())
+}
+
+fn narrow(ints: Vec<i32>) -> Value {
+    let min = ints.iter().cloned().min().unwrap_or(0);
+    let max = ints.iter().cloned().max().unwrap_or(0);
+    let fits = |lo: i32, hi: i32| lo <= min && max <= hi;
+    if fits(0, i32::from(u8::MAX)) {
+        Value::LSTU8(ints.into_iter().map(|e| e as u8).collect())
+    } else if fits(i32::from(i8::MIN), i32::from(i8::MAX)) {
+        Value::LSTI8(ints.into_iter().map(|e| e as i8).collect())
+    } else if fits(0, i32::from(u16::MAX)) {
+        Value::LSTU16(ints.into_iter().map(|e| e as u16).collect())
+    } else if fits(i32::from(i16::MIN), i32::from(i16::MAX)) {
+        Value::LSTI16(ints.into_iter().map(|e| e as i16).collect())
+    } else {
+        Value::LSTI32(ints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pack::PackOptions;
+    use Value;
+
+    #[test]
+    fn pack() {
+        let value = tson!({"f": [1.5, 2], "i": [1, -300], "s": ["a", "b"], "m": [1, "a"], "e": [], "l": [[1], ["a\0"]]});
+        let packed = value.pack(PackOptions::default());
+        assert_eq!(packed, tson!({"f": f64[1.5, 2.0], "i": i32[1, -300], "s": str["a", "b"], "m": [1, "a"], "e": [],
+            "l": [i32[1], ["a\0"]]}));
+        assert_eq!(packed.unpack().unwrap(), tson!({"f": [1.5, 2.0], "i": [1, -300], "s": ["a", "b"], "m": [1, "a"], "e": [],
+            "l": [[1], ["a\0"]]}));
+
+        let narrow = PackOptions { narrow_integers: true };
+        assert_eq!(tson!([1, 255]).pack(narrow), Value::LSTU8(vec![1, 255]));
+        assert_eq!(tson!([-1, 127]).pack(narrow), Value::LSTI8(vec![-1, 127]));
+        assert_eq!(tson!([0, 65535]).pack(narrow), Value::LSTU16(vec![0, 65535]));
+        assert_eq!(tson!([-1, 300]).pack(narrow), Value::LSTI16(vec![-1, 300]));
+        assert_eq!(tson!([0, 70000]).pack(narrow), Value::LSTI32(vec![0, 70000]));
+    }
+
+    #[test]
+    fn unpack() {
+        assert_eq!(Value::LSTU64(vec![1, 1 << 40]).unpack().unwrap(), tson!([1, 1_099_511_627_776.0]));
+        assert_eq!(Value::LSTU64(vec![u64::MAX - 1]).unpack().unwrap_err().to_string(), "18446744073709551614 does not fit I32 or F64");
+    }
+}
@@ -0,0 +1,408 @@
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::ops::Range;
+
+use ::{map_insert_at, map_remove, sorted_keys, Map, Path, PathSegment, StrVec, TsonError, TsonResult, Value};
+use diff::differing_ranges;
+use spec::{TsonType, TsonTypedListType};
+
+/// An operation of a `Patch`, addressed by path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchOp {
+    /// Inserts into a map, replacing an existing entry, or into a LST, `-` appending at its end.
+    Add { path: Path, value: Value },
+    /// Removes a map entry or a LST element.
+    Remove { path: Path },
+    /// Replaces an existing value, the whole document for an empty path.
+    Replace { path: Path, value: Value },
+    /// Replaces the `range` elements of a list by `values`, a list of the same type.
+    Splice { path: Path, range: Range<usize>, values: Value },
+}
+
+/// Operations applied in order by `Value::apply_patch`.
+///
+/// Converts to and from a LST of maps, `{"op": "splice", "path": "/a", "range": u64[0, 2], "values": f64[1.0]}`,
+/// so that it can be encoded as TSON.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Patch {
+    pub ops: Vec<PatchOp>,
+}
+
+enum Undo {
+    Op(PatchOp),
+    /// Puts back a removed map entry at its position.
+    Reinsert { parent: Path, index: usize, key: String, value: Value },
+}
+
+impl Value {
+    /// Applies all the operations or none, the value being unchanged on error.
+    pub fn apply_patch(&mut self, patch: &Patch) -> TsonResult<()> {
+        let mut undos = Vec::with_capacity(patch.ops.len());
+        for op in patch.ops.iter() {
+            match apply(self, op) {
+                Ok(undo) => undos.push(undo),
+                Err(e) => {
+                    for undo in undos.into_iter().rev() {
+                        revert(self, undo)?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Patch turning `a` into `b`.
+pub fn diff_to_patch(a: &Value, b: &Value) -> Patch {
+    let mut patch = Patch::default();
+    diff_values(a, b, &mut Path::new(), &mut patch.ops);
+    patch
+}
+
+fn op_error<T>(name: &str, path: &Path, message: T) -> TsonError where T: Display {
+    TsonError::new(format!("{} {:?} -- {}", name, path.to_string(), message))
+}
+
+fn apply(root: &mut Value, op: &PatchOp) -> TsonResult<Undo> {
+    match *op {
+        PatchOp::Add { ref path, ref value } => {
+            let (last, parent) = match path.segments().split_last() {
+                Some((last, parent)) => (last, Path::from(parent.to_vec())),
+                None => return Err(op_error("add", path, "empty path")),
+            };
+            let target = root.resolve_mut(parent.segments()).map_err(|e| op_error("add", path, e))?;
+            match *target {
+                Value::MAP(ref mut map) => match map.insert(last.as_key(), value.clone()) {
+                    Some(old) => Ok(Undo::Op(PatchOp::Replace { path: path.clone(), value: old })),
+                    None => Ok(Undo::Op(PatchOp::Remove { path: path.clone() })),
+                },
+                Value::LST(ref mut list) => {
+                    let index = match *last {
+                        PathSegment::Key(ref key) if key == "-" => list.len(),
+                        _ => match last.as_index() {
+                            Some(index) if index <= list.len() => index,
+                            _ => return Err(op_error("add", path, format!("index out of bounds -- length {}", list.len()))),
+                        },
+                    };
+                    list.insert(index, value.clone());
+                    Ok(Undo::Op(PatchOp::Remove { path: parent.index(index) }))
+                }
+                ref other => Err(op_error("add", path, format!("cannot add to {}", other.tson_type()))),
+            }
+        }
+        PatchOp::Remove { ref path } => {
+            let (last, parent) = match path.segments().split_last() {
+                Some((last, parent)) => (last, Path::from(parent.to_vec())),
+                None => return Err(op_error("remove", path, "empty path")),
+            };
+            let target = root.resolve_mut(parent.segments()).map_err(|e| op_error("remove", path, e))?;
+            match *target {
+                Value::MAP(ref mut map) => {
+                    let key = last.as_key();
+                    match map_remove(map, &key) {
+                        Some((index, value)) => Ok(Undo::Reinsert { parent, index, key, value }),
+                        None => Err(op_error("remove", path, "not found")),
+                    }
+                }
+                Value::LST(ref mut list) => match last.as_index() {
+                    Some(index) if index < list.len() => {
+                        let value = list.remove(index);
+                        Ok(Undo::Op(PatchOp::Add { path: parent.index(index), value }))
+                    }
+                    _ => Err(op_error("remove", path, format!("index out of bounds -- length {}", list.len()))),
+                },
+                ref other => Err(op_error("remove", path, format!("cannot remove from {}", other.tson_type()))),
+            }
+        }
+        PatchOp::Replace { ref path, ref value } => {
+            let target = root.resolve_mut(path.segments()).map_err(|e| op_error("replace", path, e))?;
+            let old = std::mem::replace(target, value.clone());
+            Ok(Undo::Op(PatchOp::Replace { path: path.clone(), value: old }))
+        }
+        PatchOp::Splice { ref path, ref range, ref values } => {
+            let (range, values) = (range.clone(), values.clone());
+            let target = root.resolve_mut(path.segments()).map_err(|e| op_error("splice", path, e))?;
+            let (range, removed) = match target.tson_type() {
+                TsonType::LST => Vec::<Value>::try_from(values)
+                    .and_then(|values| splice(target.to_list_mut().expect("LST"), range, values))
+                    .map(|(range, removed)| (range, Value::LST(removed))),
+                TsonType::LSTU8 => splice_typed::<u8>(target, range, values),
+                TsonType::LSTI8 => splice_typed::<i8>(target, range, values),
+                TsonType::LSTU16 => splice_typed::<u16>(target, range, values),
+                TsonType::LSTI16 => splice_typed::<i16>(target, range, values),
+                TsonType::LSTU32 => splice_typed::<u32>(target, range, values),
+                TsonType::LSTI32 => splice_typed::<i32>(target, range, values),
+                TsonType::LSTU64 => splice_typed::<u64>(target, range, values),
+                TsonType::LSTI64 => splice_typed::<i64>(target, range, values),
+                TsonType::LSTF32 => splice_typed::<f32>(target, range, values),
+                TsonType::LSTF64 => splice_typed::<f64>(target, range, values),
+                TsonType::LSTSTR => splice_str_vec(target, range, values),
+                other => Err(TsonError::new(format!("cannot splice {}", other))),
+            }.map_err(|e| op_error("splice", path, e))?;
+            Ok(Undo::Op(PatchOp::Splice { path: path.clone(), range, values: removed }))
+        }
+    }
+}
+
+fn revert(root: &mut Value, undo: Undo) -> TsonResult<()> {
+    match undo {
+        Undo::Op(op) => apply(root, &op).map(|_| ()),
+        Undo::Reinsert { parent, index, key, value } => {
+            match *root.resolve_mut(parent.segments())? {
+                Value::MAP(ref mut map) => {
+                    map_insert_at(map, index, key, value);
+                    Ok(())
+                }
+                ref other => Err(other.type_error(TsonType::MAP)),
+            }
+        }
+    }
+}
+
+/// Gives the range of the inserted values and the removed elements.
+fn splice<T>(vec: &mut Vec<T>, range: Range<usize>, values: Vec<T>) -> TsonResult<(Range<usize>, Vec<T>)> {
+    if range.start > range.end || range.end > vec.len() {
+        return Err(TsonError::new(format!("range {}..{} out of bounds -- length {}", range.start, range.end, vec.len())));
+    }
+    let inserted = range.start..range.start + values.len();
+    let removed = vec.splice(range, values).collect();
+    Ok((inserted, removed))
+}
+
+fn splice_typed<T>(target: &mut Value, range: Range<usize>, values: Value) -> TsonResult<(Range<usize>, Value)>
+    where T: TsonTypedListType {
+    let values = T::try_from_value(values).map_err(|values| values.type_error(T::tson_type()))?;
+    let vec = T::as_vec_mut(target).expect("typed list");
+    let (range, removed) = splice(vec, range, values)?;
+    Ok((range, T::into_value(removed)))
+}
+
+fn splice_str_vec(target: &mut Value, range: Range<usize>, values: Value) -> TsonResult<(Range<usize>, Value)> {
+    let values = StrVec::try_from(values)?;
+    let inserted = values.build_starts()?.len() - 1;
+    let strings = match *target {
+        Value::LSTSTR(ref mut strings) => strings,
+        _ => unreachable!(),
+    };
+    // Splices the bytes of the strings, terminators included.
+    let starts = strings.build_starts()?;
+    let len = starts.len() - 1;
+    if range.start > range.end || range.end > len {
+        return Err(TsonError::new(format!("range {}..{} out of bounds -- length {}", range.start, range.end, len)));
+    }
+    let inserted = range.start..range.start + inserted;
+    let removed = strings.bytes.splice(starts[range.start]..starts[range.end], values.bytes).collect();
+    Ok((inserted, Value::LSTSTR(StrVec::from_bytes_unchecked(removed))))
+}
+
+fn diff_values(a: &Value, b: &Value, path: &mut Path, ops: &mut Vec<PatchOp>) {
+    match (a, b) {
+        (Value::MAP(a), Value::MAP(b)) => {
+            for key in sorted_keys(a, b) {
+                path.push(PathSegment::Key(key.clone()));
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => diff_values(a, b, path, ops),
+                    (Some(_), None) => ops.push(PatchOp::Remove { path: path.clone() }),
+                    (None, Some(b)) => ops.push(PatchOp::Add { path: path.clone(), value: b.clone() }),
+                    (None, None) => unreachable!(),
+                }
+                path.pop();
+            }
+        }
+        (Value::LST(a), Value::LST(b)) => {
+            for (i, (a, b)) in a.iter().zip(b.iter()).enumerate() {
+                path.push(PathSegment::Index(i));
+                diff_values(a, b, path, ops);
+                path.pop();
+            }
+            if a.len() != b.len() {
+                let common = a.len().min(b.len());
+                ops.push(PatchOp::Splice { path: path.clone(), range: common..a.len(), values: Value::LST(b[common..].to_vec()) });
+            }
+        }
+        (Value::LSTU8(a), Value::LSTU8(b)) => diff_typed(a, b, |a, b| a == b, path, ops),
+        (Value::LSTI8(a), Value::LSTI8(b)) => diff_typed(a, b, |a, b| a == b, path, ops),
+        (Value::LSTU16(a), Value::LSTU16(b)) => diff_typed(a, b, |a, b| a == b, path, ops),
+        (Value::LSTI16(a), Value::LSTI16(b)) => diff_typed(a, b, |a, b| a == b, path, ops),
+        (Value::LSTU32(a), Value::LSTU32(b)) => diff_typed(a, b, |a, b| a == b, path, ops),
+        (Value::LSTI32(a), Value::LSTI32(b)) => diff_typed(a, b, |a, b| a == b, path, ops),
+        (Value::LSTU64(a), Value::LSTU64(b)) => diff_typed(a, b, |a, b| a == b, path, ops),
+        (Value::LSTI64(a), Value::LSTI64(b)) => diff_typed(a, b, |a, b| a == b, path, ops),
+        (Value::LSTF32(a), Value::LSTF32(b)) => diff_typed(a, b, |a, b| a.to_bits() == b.to_bits(), path, ops),
+        (Value::LSTF64(a), Value::LSTF64(b)) => diff_typed(a, b, |a, b| a.to_bits() == b.to_bits(), path, ops),
+        (Value::LSTSTR(a_strings), Value::LSTSTR(b_strings)) => {
+            let (a_starts, b_starts) = match (a_strings.build_starts(), b_strings.build_starts()) {
+                (Ok(a_starts), Ok(b_starts)) => (a_starts, b_starts),
+                _ => return ops.push(PatchOp::Replace { path: path.clone(), value: b.clone() }),
+            };
+            let string = |strings: &StrVec, starts: &[usize], i: usize| strings.bytes[starts[i]..starts[i + 1]].to_vec();
+            let a: Vec<Vec<u8>> = (0..a_starts.len() - 1).map(|i| string(a_strings, &a_starts, i)).collect();
+            let b: Vec<Vec<u8>> = (0..b_starts.len() - 1).map(|i| string(b_strings, &b_starts, i)).collect();
+            let values = |range: Range<usize>| {
+                Value::LSTSTR(StrVec::from_bytes_unchecked(b_strings.bytes[b_starts[range.start]..b_starts[range.end]].to_vec()))
+            };
+            for range in differing_ranges(&a, &b, |a, b| a == b) {
+                ops.push(PatchOp::Splice { path: path.clone(), range: range.clone(), values: values(range) });
+            }
+            if a.len() != b.len() {
+                let common = a.len().min(b.len());
+                ops.push(PatchOp::Splice { path: path.clone(), range: common..a.len(), values: values(common..b.len()) });
+            }
+        }
+        _ => {
+            if a.total_cmp(b) != ::std::cmp::Ordering::Equal {
+                ops.push(PatchOp::Replace { path: path.clone(), value: b.clone() });
+            }
+        }
+    }
+}
+
+fn diff_typed<T, F>(a: &[T], b: &[T], eq: F, path: &Path, ops: &mut Vec<PatchOp>)
+    where T: TsonTypedListType, F: Fn(&T, &T) -> bool {
+    for range in differing_ranges(a, b, eq) {
+        ops.push(PatchOp::Splice { path: path.clone(), range: range.clone(), values: T::into_value(b[range].to_vec()) });
+    }
+    if a.len() != b.len() {
+        let common = a.len().min(b.len());
+        ops.push(PatchOp::Splice { path: path.clone(), range: common..a.len(), values: T::into_value(b[common..].to_vec()) });
+    }
+}
+
+impl From<Patch> for Value {
+    fn from(patch: Patch) -> Self {
+        Value::LST(patch.ops.into_iter().map(|op| {
+            let mut map = Map::new();
+            let (name, path) = match op {
+                PatchOp::Add { path, value } => {
+                    map.insert("value".to_owned(), value);
+                    ("add", path)
+                }
+                PatchOp::Remove { path } => ("remove", path),
+                PatchOp::Replace { path, value } => {
+                    map.insert("value".to_owned(), value);
+                    ("replace", path)
+                }
+                PatchOp::Splice { path, range, values } => {
+                    map.insert("range".to_owned(), Value::LSTU64(vec![range.start as u64, range.end as u64]));
+                    map.insert("values".to_owned(), values);
+                    ("splice", path)
+                }
+            };
+            map.insert("op".to_owned(), Value::STR(name.to_owned()));
+            map.insert("path".to_owned(), Value::STR(path.to_string()));
+            Value::MAP(map)
+        }).collect())
+    }
+}
+
+impl TryFrom<Value> for Patch {
+    type Error = TsonError;
+
+    fn try_from(value: Value) -> TsonResult<Self> {
+        let ops = Vec::<Value>::try_from(value)?.into_iter().map(|op| {
+            let mut map = Map::<String, Value>::try_from(op)
+                .map_err(|e| TsonError::new(format!("wrong format -- patch -- {}", e)))?;
+            let mut field = |name: &str| {
+                map_remove(&mut map, name).map(|(_, value)| value)
+                    .ok_or_else(|| TsonError::new(format!("wrong format -- patch -- missing {}", name)))
+            };
+            let name = String::try_from(field("op")?)?;
+            let path = String::try_from(field("path")?)?.parse::<Path>()?;
+            match name.as_str() {
+                "add" => Ok(PatchOp::Add { path, value: field("value")? }),
+                "remove" => Ok(PatchOp::Remove { path }),
+                "replace" => Ok(PatchOp::Replace { path, value: field("value")? }),
+                "splice" => {
+                    let range = Vec::<u64>::try_from(field("range")?)?;
+                    if range.len() != 2 {
+                        return Err(TsonError::new("wrong format -- patch -- range u64[start, end] expected"));
+                    }
+                    Ok(PatchOp::Splice { path, range: range[0] as usize..range[1] as usize, values: field("values")? })
+                }
+                _ => Err(TsonError::new(format!("wrong format -- patch -- unknown op {:?}", name))),
+            }
+        }).collect::<TsonResult<Vec<PatchOp>>>()?;
+        Ok(Patch { ops })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use patch::{diff_to_patch, Patch, PatchOp};
+    use {decode_bytes, encode, StrVec, TsonResult, Value};
+
+    #[test]
+    fn diff_and_apply() -> TsonResult<()> {
+        let a = tson!({
+            "name": "a", "gone": 1, "list": [1, {"x": 1}, 3], "values": f64[1.0, 2.0, 3.0, 4.0],
+            "levels": str["a", "b", "c"], "type": 1
+        });
+        let b = tson!({
+            "name": "b", "new": [true], "list": [1, {"x": 2}], "values": f64[1.0, 0.0, 3.0, 4.0, 5.0],
+            "levels": str["a", "bb"], "type": "1"
+        });
+        let patch = diff_to_patch(&a, &b);
+        let mut value = a.clone();
+        value.apply_patch(&patch)?;
+        assert_eq!(value, b);
+
+        let mut value = tson!([1, 2]);
+        value.apply_patch(&diff_to_patch(&value.clone(), &tson!({"a": 1})))?;
+        assert_eq!(value, tson!({"a": 1}));
+
+        let mut value = tson!({"a": [1]});
+        value.apply_patch(&Patch { ops: vec![PatchOp::Add { path: "/a/-".parse()?, value: tson!(2) }] })?;
+        assert_eq!(value, tson!({"a": [1, 2]}));
+        Ok(())
+    }
+
+    #[test]
+    fn atomic() -> TsonResult<()> {
+        let original = tson!({"a": 1, "b": [1, 2], "c": f64[1.0, 2.0], "d": {"e": null}});
+        let patch = Patch {
+            ops: vec![
+                PatchOp::Remove { path: "/a".parse()? },
+                PatchOp::Splice { path: "/c".parse()?, range: 0..1, values: tson!(f64[5.0, 6.0]) },
+                PatchOp::Add { path: "/b/0".parse()?, value: tson!(0) },
+                PatchOp::Replace { path: "/d/e".parse()?, value: tson!(true) },
+                PatchOp::Remove { path: "/d/missing/x".parse()? },
+            ],
+        };
+        let mut value = original.clone();
+        let error = value.apply_patch(&patch).unwrap_err();
+        assert_eq!(error.to_string(), r#"remove "/d/missing/x" -- /d/missing -- not found"#);
+        assert_eq!(value, original);
+
+        let patch = Patch { ops: vec![PatchOp::Splice { path: "/c".parse()?, range: 0..1, values: tson!(i32[1]) }] };
+        let error = value.apply_patch(&patch).unwrap_err();
+        assert_eq!(error.to_string(), r#"splice "/c" -- LSTF64 expected -- found LSTI32"#);
+
+        let mut value = tson!({"s": str["a"]});
+        let values = Value::LSTSTR(StrVec::from_bytes_unchecked(b"b".to_vec()));
+        let patch = Patch { ops: vec![PatchOp::Splice { path: "/s".parse()?, range: 1..1, values }] };
+        assert!(value.apply_patch(&patch).is_err());
+        assert_eq!(value, tson!({"s": str["a"]}));
+        Ok(())
+    }
+
+    #[test]
+    fn encode_patch() -> TsonResult<()> {
+        let patch = diff_to_patch(&tson!({"a": 1, "b": u8[1, 2], "c/d": null}), &tson!({"b": u8[1], "c/d": 2, "e": "x"}));
+        let value = Value::from(patch.clone());
+        assert_eq!(value[1], tson!({"op": "splice", "path": "/b", "range": u64[1, 2], "values": u8[]}));
+        assert_eq!(value[2]["path"], tson!("/c~1d"));
+        assert_eq!(Patch::try_from(decode_bytes(&encode(&value)?)?)?, patch);
+
+        assert!(Patch::try_from(tson!([{"op": "move", "path": ""}])).is_err());
+
+        // "/01" is a key, not the index 1.
+        let (a, b) = (tson!({"01": 1, "1": 2}), tson!({"01": 3, "1": 4}));
+        let patch = Patch::try_from(decode_bytes(&encode(&Value::from(diff_to_patch(&a, &b)))?)?)?;
+        let mut value = a.clone();
+        value.apply_patch(&patch)?;
+        assert_eq!(value, b);
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::str::FromStr;
+
+use ::{TsonError, TsonResult, Value};
+use spec::TsonType;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Location of a value inside a document, displayed in pointer syntax (`/columns/2/name`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Path {
+    segments: Vec<PathSegment>,
+}
+
+impl Path {
+    pub fn new() -> Path {
+        Path { segments: Vec::new() }
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn push(&mut self, segment: PathSegment) {
+        self.segments.push(segment);
+    }
+
+    pub fn pop(&mut self) -> Option<PathSegment> {
+        self.segments.pop()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.segments.truncate(len);
+    }
+
+    pub fn extend<I>(&mut self, segments: I) where I: IntoIterator<Item = PathSegment> {
+        self.segments.extend(segments);
+    }
+
+    pub fn key<T>(&self, key: T) -> Path where T: Into<String> {
+        let mut path = self.clone();
+        path.push(PathSegment::Key(key.into()));
+        path
+    }
+
+    pub fn index(&self, index: usize) -> Path {
+        let mut path = self.clone();
+        path.push(PathSegment::Index(index));
+        path
+    }
+
+    /// Pointer syntax for reports, the root being shown as `/` rather than as the empty pointer.
+    pub(crate) fn to_display_string(&self) -> String {
+        if self.is_empty() { "/".to_owned() } else { self.to_string() }
+    }
+}
+
+impl From<Vec<PathSegment>> for Path {
+    fn from(segments: Vec<PathSegment>) -> Self {
+        Path { segments }
+    }
+}
+
+/// Parses the pointer syntax, an empty string being the root and array indices, `0` or digits without
+/// a leading zero, giving an `Index`.
+impl FromStr for Path {
+    type Err = TsonError;
+
+    fn from_str(pointer: &str) -> TsonResult<Path> {
+        if pointer.is_empty() {
+            return Ok(Path::new());
+        }
+        if !pointer.starts_with('/') {
+            return Err(TsonError::new(format!("wrong format -- pointer {:?} does not start with /", pointer)));
+        }
+        let segments = pointer[1..].split('/').map(|segment| {
+            match parse_index(segment) {
+                Some(index) => PathSegment::Index(index),
+                None => PathSegment::Key(segment.replace("~1", "/").replace("~0", "~")),
+            }
+        });
+        Ok(Path { segments: segments.collect() })
+    }
+}
+
+fn parse_index(segment: &str) -> Option<usize> {
+    let digits = !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit());
+    if digits && (segment == "0" || !segment.starts_with('0')) {
+        segment.parse().ok()
+    } else {
+        None
+    }
+}
+
+impl PathSegment {
+    /// Index of a list element, keys written as array indices being accepted too.
+    pub(crate) fn as_index(&self) -> Option<usize> {
+        match *self {
+            PathSegment::Index(index) => Some(index),
+            PathSegment::Key(ref key) => parse_index(key),
+        }
+    }
+
+    /// Key of a map entry, indices being used as keys too.
+    pub(crate) fn as_key(&self) -> String {
+        match *self {
+            PathSegment::Key(ref key) => key.clone(),
+            PathSegment::Index(index) => index.to_string(),
+        }
+    }
+}
+
+impl Value {
+    /// Value at `segments`, the error naming the first segment that cannot be followed.
+    pub(crate) fn resolve(&self, segments: &[PathSegment]) -> TsonResult<&Value> {
+        let mut value = self;
+        for (i, segment) in segments.iter().enumerate() {
+            let child = match *value {
+                Value::MAP(ref map) => map.get(&segment.as_key()),
+                Value::LST(ref list) => segment.as_index().and_then(|index| list.get(index)),
+                _ => None,
+            };
+            value = child.ok_or_else(|| segment_error(&segments[..=i], value.tson_type()))?;
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn resolve_mut(&mut self, segments: &[PathSegment]) -> TsonResult<&mut Value> {
+        let mut value = self;
+        for (i, segment) in segments.iter().enumerate() {
+            let tson_type = value.tson_type();
+            let child = match *value {
+                Value::MAP(ref mut map) => map.get_mut(&segment.as_key()),
+                Value::LST(ref mut list) => segment.as_index().and_then(move |index| list.get_mut(index)),
+                _ => None,
+            };
+            value = child.ok_or_else(|| segment_error(&segments[..=i], tson_type))?;
+        }
+        Ok(value)
+    }
+}
+
+/// Error for the last of `segments`, not found in a MAP or LST, or followed into another type.
+pub(crate) fn segment_error(segments: &[PathSegment], tson_type: TsonType) -> TsonError {
+    let path = Path::from(segments.to_vec());
+    match tson_type {
+        TsonType::MAP | TsonType::LST => TsonError::new(format!("{} -- not found", path)),
+        _ => TsonError::new(format!("{} -- cannot index {}", path, tson_type)),
+    }
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathSegment::Key(ref key) => write!(f, "{}", key.replace('~', "~0").replace('/', "~1")),
+            PathSegment::Index(index) => write!(f, "{}", index),
+        }
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for segment in self.segments.iter() {
+            write!(f, "/{}", segment)?;
+        }
+        Ok(())
+    }
+}
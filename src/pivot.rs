@@ -0,0 +1,268 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use ::{Map, StrVec, TsonError, TsonResult, Value};
+use pointer::{get_element, string_value};
+use spec::TsonType;
+use table::column_len;
+
+/// How `rows_to_columns` handles a column holding values of different types, NULL aside.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MixedTypes {
+    /// I32 and F64 give a LSTF64, other mixes a generic LST.
+    Widen,
+    /// Any mix gives a generic LST.
+    Generic,
+    /// Any mix fails.
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PivotOptions {
+    pub mixed: MixedTypes,
+}
+
+impl Default for PivotOptions {
+    fn default() -> Self {
+        PivotOptions { mixed: MixedTypes::Widen }
+    }
+}
+
+static NULL: Value = Value::NULL;
+
+impl Value {
+    /// Converts a LST of MAPs into a MAP of columns, named after the keys found in the rows.
+    ///
+    /// I32, F64 and STR columns give LSTI32, LSTF64 and LSTSTR, other columns a generic LST.
+    /// A typed list holding no NULL, a column with a NULL or a missing key is a generic LST,
+    /// a missing key giving NULL.
+    pub fn rows_to_columns(&self, options: PivotOptions) -> TsonResult<Value> {
+        let rows = match *self {
+            Value::LST(ref rows) => rows,
+            _ => return Err(self.type_error(TsonType::LST)),
+        };
+        let mut names: Vec<&str> = Vec::new();
+        let mut indices: HashMap<&str, usize> = HashMap::new();
+        let mut columns: Vec<Vec<&Value>> = Vec::new();
+        for (i, row) in rows.iter().enumerate() {
+            let row = match *row {
+                Value::MAP(ref row) => row,
+                _ => return Err(TsonError::new(format!("row {} -- {}", i, row.type_error(TsonType::MAP)))),
+            };
+            for (name, value) in row.iter() {
+                let index = *indices.entry(name).or_insert_with(|| {
+                    names.push(name);
+                    columns.push(vec![&NULL; i]);
+                    columns.len() - 1
+                });
+                columns[index].push(value);
+            }
+            for column in columns.iter_mut().filter(|c| c.len() == i) {
+                column.push(&NULL);
+            }
+        }
+        names.into_iter().zip(columns)
+            .map(|(name, values)| to_column(name, &values, options).map(|column| (name.to_owned(), column)))
+            .collect::<TsonResult<Map<_, _>>>()
+            .map(Value::MAP)
+    }
+
+    /// Converts a MAP of columns, typed lists, LSTSTR or LST of the same length, into a LST of MAPs.
+    pub fn columns_to_rows(&self) -> TsonResult<Value> {
+        self.rows()?.map(|row| row.to_value()).collect::<TsonResult<Vec<_>>>().map(Value::LST)
+    }
+
+    /// Views of the rows of a MAP of columns.
+    pub fn rows(&self) -> TsonResult<Rows<'_>> {
+        let map = match *self {
+            Value::MAP(ref map) => map,
+            _ => return Err(self.type_error(TsonType::MAP)),
+        };
+        let mut len = None;
+        let mut columns = Vec::with_capacity(map.len());
+        for (name, values) in map.iter() {
+            let column_len = match *values {
+                Value::LST(ref list) => list.len(),
+                _ => column_len(values).ok_or_else(|| {
+                    TsonError::new(format!("column \"{}\" -- list expected -- found {}", name, values.tson_type()))
+                })?,
+            };
+            match len {
+                Some(len) if len != column_len => {
+                    return Err(TsonError::new(format!("column \"{}\" -- length {} expected -- found {}", name, len, column_len)));
+                }
+                _ => len = Some(column_len),
+            }
+            let starts = match *values {
+                Value::LSTSTR(ref strings) => Some(strings.build_starts()?),
+                _ => None,
+            };
+            columns.push(Column { name, values, starts });
+        }
+        Ok(Rows { columns: Rc::new(columns), len: len.unwrap_or(0), index: 0 })
+    }
+}
+
+fn to_column(name: &str, values: &[&Value], options: PivotOptions) -> TsonResult<Value> {
+    let mut types: Vec<TsonType> = Vec::new();
+    for value in values {
+        let tson_type = value.tson_type();
+        if tson_type != TsonType::NULL && !types.contains(&tson_type) {
+            types.push(tson_type);
+        }
+    }
+    let has_nulls = values.iter().any(|v| v.is_null());
+    let generic = || Value::LST(values.iter().map(|v| (*v).clone()).collect());
+    let floats = || Value::LSTF64(values.iter().filter_map(|v| match **v {
+        Value::I32(v) => Some(f64::from(v)),
+        Value::F64(v) => Some(v),
+        _ => None,
+    }).collect());
+    match types.len() {
+        0 => return Ok(generic()),
+        1 => {}
+        _ => return match options.mixed {
+            MixedTypes::Widen if !has_nulls && types.iter().all(|t| *t == TsonType::I32 || *t == TsonType::F64) => Ok(floats()),
+            MixedTypes::Widen | MixedTypes::Generic => Ok(generic()),
+            MixedTypes::Error => {
+                let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+                Err(TsonError::new(format!("column \"{}\" -- mixed types -- {}", name, types.join(" and "))))
+            }
+        },
+    }
+    Ok(match types[0] {
+        // A typed list cannot hold a NULL.
+        _ if has_nulls => generic(),
+        TsonType::I32 => Value::LSTI32(values.iter().filter_map(|v| v.as_i32().ok()).collect()),
+        TsonType::F64 => floats(),
+        // A LSTSTR cannot hold a nul byte.
+        TsonType::STR if !values.iter().any(|v| matches!(**v, Value::STR(ref s) if s.contains('\0'))) => {
+            let strings: Vec<String> = values.iter().map(|v| match **v {
+                Value::STR(ref s) => s.clone(),
+                _ => String::new(),
+            }).collect();
+            Value::LSTSTR(StrVec::from(strings))
+        }
+        _ => generic(),
+    })
+}
+
+struct Column<'a> {
+    name: &'a str,
+    values: &'a Value,
+    /// Element starts of a LSTSTR, computed once for all the rows.
+    starts: Option<Vec<usize>>,
+}
+
+impl<'a> Column<'a> {
+    fn get(&self, index: usize) -> TsonResult<Cow<'a, Value>> {
+        match (self.values, &self.starts) {
+            (Value::LST(list), _) => Ok(Cow::Borrowed(&list[index])),
+            (Value::LSTSTR(strings), Some(starts)) => {
+                string_value(&strings.bytes[starts[index]..starts[index + 1] - 1]).map(Cow::Owned)
+            }
+            (values, _) => get_element(values, index).unwrap_or(Ok(Value::NULL)).map(Cow::Owned),
+        }
+    }
+}
+
+/// Iterator over the rows of a MAP of columns.
+pub struct Rows<'a> {
+    columns: Rc<Vec<Column<'a>>>,
+    len: usize,
+    index: usize,
+}
+
+impl<'a> Iterator for Rows<'a> {
+    type Item = RowView<'a>;
+
+    fn next(&mut self) -> Option<RowView<'a>> {
+        if self.index == self.len {
+            return None;
+        }
+        self.index += 1;
+        Some(RowView { columns: self.columns.clone(), index: self.index - 1 })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len - self.index, Some(self.len - self.index))
+    }
+}
+
+/// Row of a MAP of columns, borrowing the columns.
+pub struct RowView<'a> {
+    columns: Rc<Vec<Column<'a>>>,
+    index: usize,
+}
+
+impl<'a> RowView<'a> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Element of the row in column `name`, typed elements being converted as by `Value::pointer`.
+    pub fn get(&self, name: &str) -> TsonResult<Cow<'a, Value>> {
+        match self.columns.iter().find(|c| c.name == name) {
+            Some(column) => column.get(self.index),
+            None => Err(TsonError::new(format!("column \"{}\" -- not found", name))),
+        }
+    }
+
+    pub fn to_value(&self) -> TsonResult<Value> {
+        self.columns.iter()
+            .map(|c| c.get(self.index).map(|v| (c.name.to_owned(), v.into_owned())))
+            .collect::<TsonResult<Map<_, _>>>()
+            .map(Value::MAP)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pivot::{MixedTypes, PivotOptions};
+
+    #[test]
+    fn rows_to_columns() {
+        let rows = tson!([{"i": 1, "f": 1, "s": "a", "b": true}, {"i": 2, "f": 2.5, "n": null, "b": false}, {"i": 3, "f": null, "s": "c"}]);
+        let columns = rows.rows_to_columns(PivotOptions::default()).unwrap();
+        let map = &columns;
+        assert_eq!(map["i"], tson!(i32[1, 2, 3]));
+        assert_eq!(map["s"], tson!(["a", null, "c"]));
+        assert_eq!(map["b"], tson!([true, false, null]));
+        assert_eq!(map["n"], tson!([null, null, null]));
+        assert_eq!(map["f"], tson!([1, 2.5, null]));
+
+        let rows = tson!([{"i": 1, "f": 1.5}, {"i": null, "f": 2}, {"f": 0.5}]);
+        let columns = rows.rows_to_columns(PivotOptions::default()).unwrap();
+        assert_eq!(columns, tson!({"i": [1, null, null], "f": f64[1.5, 2.0, 0.5]}));
+        assert_eq!(columns.columns_to_rows().unwrap(), tson!([{"i": 1, "f": 1.5}, {"i": null, "f": 2.0}, {"i": null, "f": 0.5}]));
+
+        let strings = tson!([{"s": "a"}, {"s": ""}]);
+        let columns = strings.rows_to_columns(PivotOptions::default()).unwrap();
+        assert_eq!(columns, tson!({"s": str["a", ""]}));
+        assert_eq!(columns.columns_to_rows().unwrap(), strings);
+        assert_eq!(tson!({"s": ["a", null]}).columns_to_rows().unwrap(), tson!([{"s": "a"}, {"s": null}]));
+
+        let mixed = tson!([{"a": 1}, {"a": "x"}]);
+        assert_eq!(mixed.rows_to_columns(PivotOptions::default()).unwrap(), tson!({"a": [1, "x"]}));
+        let options = PivotOptions { mixed: MixedTypes::Error };
+        assert_eq!(mixed.rows_to_columns(options).unwrap_err().to_string(), "column \"a\" -- mixed types -- I32 and STR");
+        assert!(tson!([{"a": 1}, {"a": "x"}, {}]).rows_to_columns(options).is_err());
+        let options = PivotOptions { mixed: MixedTypes::Generic };
+        assert_eq!(tson!([{"a": 1}, {"a": 1.5}]).rows_to_columns(options).unwrap(), tson!({"a": [1, 1.5]}));
+        assert_eq!(tson!([{"a": 1}, 2]).rows_to_columns(options).unwrap_err().to_string(), "row 1 -- MAP expected -- found I32");
+    }
+
+    #[test]
+    fn columns_to_rows() {
+        let columns = tson!({"a": i32[1, 2], "s": str["x", "y"], "l": [true, null]});
+        assert_eq!(columns.columns_to_rows().unwrap(), tson!([{"a": 1, "s": "x", "l": true}, {"a": 2, "s": "y", "l": null}]));
+        let rows: Vec<_> = columns.rows().unwrap().collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(*rows[1].get("s").unwrap(), tson!("y"));
+        assert_eq!(rows[1].index(), 1);
+        assert_eq!(rows[0].get("z").unwrap_err().to_string(), "column \"z\" -- not found");
+        assert!(tson!({"a": i32[1], "b": f64[]}).rows().is_err());
+        assert!(tson!({"a": i32[1], "b": 1}).rows().is_err());
+    }
+}
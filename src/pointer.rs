@@ -0,0 +1,325 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+
+use ::{map_remove, Map, Path, PathSegment, TsonError, TsonResult, Value};
+use path::segment_error;
+use spec::TsonType;
+
+impl Value {
+    /// Value at `pointer`, in RFC 6901 syntax (`/columns/2/values`).
+    ///
+    /// Elements of typed lists and LSTSTR have no `Value` of their own and are given as an
+    /// owned `F64`, `STR`, or `I32` for integers, integers out of the I32 range giving an `F64`
+    /// when exact, and otherwise a LSTU64 or LSTI64 of that single element.
+    pub fn pointer(&self, pointer: &str) -> TsonResult<Cow<'_, Value>> {
+        let path: Path = pointer.parse()?;
+        let (last, parent) = match path.segments().split_last() {
+            Some(split) => split,
+            None => return Ok(Cow::Borrowed(self)),
+        };
+        let target = self.resolve(parent)?;
+        match *target {
+            Value::MAP(_) | Value::LST(_) => self.resolve(path.segments()).map(Cow::Borrowed),
+            _ => {
+                let index = element_index(last, path.segments(), target)?;
+                let element = get_element(target, index).ok_or_else(|| segment_error(path.segments(), TsonType::LST))?;
+                element.map(Cow::Owned)
+            }
+        }
+    }
+
+    /// Value at `pointer`, which cannot be an element of a typed list or LSTSTR.
+    pub fn pointer_mut(&mut self, pointer: &str) -> TsonResult<&mut Value> {
+        let path: Path = pointer.parse()?;
+        self.resolve_mut(path.segments())
+    }
+
+    /// Sets the value at `pointer`, creating the missing maps on the way, NULL values becoming maps.
+    ///
+    /// The index after the last element appends to a list, as `-` does for a LST, and typed list
+    /// elements are set from an `I32` or `F64` converting exactly to their type.
+    pub fn set_at(&mut self, pointer: &str, value: Value) -> TsonResult<()> {
+        let path: Path = pointer.parse()?;
+        let (last, parent) = match path.segments().split_last() {
+            Some(split) => split,
+            None => {
+                *self = value;
+                return Ok(());
+            }
+        };
+        let mut target = self;
+        for (i, segment) in parent.iter().enumerate() {
+            if target.is_null() {
+                *target = Value::MAP(Map::new());
+            }
+            let tson_type = target.tson_type();
+            let child = match *target {
+                Value::MAP(ref mut map) => Some(map.entry(segment.as_key()).or_insert(Value::NULL)),
+                Value::LST(ref mut list) => segment.as_index().and_then(move |index| list.get_mut(index)),
+                _ => None,
+            };
+            target = child.ok_or_else(|| segment_error(&parent[..=i], tson_type))?;
+        }
+        if target.is_null() {
+            *target = Value::MAP(Map::new());
+        }
+        match *target {
+            Value::MAP(ref mut map) => {
+                map.insert(last.as_key(), value);
+                Ok(())
+            }
+            Value::LST(ref mut list) => {
+                let index = match *last {
+                    PathSegment::Key(ref key) if key == "-" => list.len(),
+                    _ => last.as_index().ok_or_else(|| index_expected(path.segments()))?,
+                };
+                match index.cmp(&list.len()) {
+                    ::std::cmp::Ordering::Less => list[index] = value,
+                    ::std::cmp::Ordering::Equal => list.push(value),
+                    ::std::cmp::Ordering::Greater => return Err(segment_error(path.segments(), TsonType::LST)),
+                }
+                Ok(())
+            }
+            ref mut target => {
+                let index = element_index(last, path.segments(), target)?;
+                set_element(target, index, value).map_err(|e| TsonError::new(format!("{} -- {}", path, e)))
+            }
+        }
+    }
+
+    /// Removes and gives the value at `pointer`, a map entry, or an element of any list.
+    pub fn remove_at(&mut self, pointer: &str) -> TsonResult<Value> {
+        let path: Path = pointer.parse()?;
+        let (last, parent) = match path.segments().split_last() {
+            Some(split) => split,
+            None => return Err(TsonError::new("remove_at -- cannot remove the root")),
+        };
+        let target = self.resolve_mut(parent)?;
+        let removed = match *target {
+            Value::MAP(ref mut map) => map_remove(map, &last.as_key()).map(|(_, value)| value),
+            Value::LST(ref mut list) => last.as_index().filter(|index| *index < list.len()).map(|index| list.remove(index)),
+            ref mut target => {
+                let index = element_index(last, path.segments(), target)?;
+                remove_element(target, index).transpose()?
+            }
+        };
+        removed.ok_or_else(|| segment_error(path.segments(), TsonType::LST))
+    }
+}
+
+/// Index of an element of `target`, the error naming `segments`.
+fn element_index(segment: &PathSegment, segments: &[PathSegment], target: &Value) -> TsonResult<usize> {
+    match target.tson_type() {
+        TsonType::NULL | TsonType::STR | TsonType::I32 | TsonType::F64 | TsonType::BOOL | TsonType::MAP => {
+            Err(segment_error(segments, target.tson_type()))
+        }
+        _ => segment.as_index().ok_or_else(|| index_expected(segments)),
+    }
+}
+
+fn index_expected(segments: &[PathSegment]) -> TsonError {
+    TsonError::new(format!("{} -- index expected", Path::from(segments.to_vec())))
+}
+
+pub(crate) fn int_value<T>(element: T) -> TsonResult<Value> where T: Into<i128> {
+    let element = element.into();
+    match i32::try_from(element) {
+        Ok(v) => Ok(Value::I32(v)),
+        Err(_) if element as f64 as i128 == element => Ok(Value::F64(element as f64)),
+        Err(_) => Err(TsonError::new(format!("{} does not fit I32 or F64", element))),
+    }
+}
+
+/// Like `int_value`, a 64 bits element held by neither giving a single element typed list.
+fn u64_value(element: u64) -> Value {
+    int_value(element).unwrap_or_else(|_| Value::LSTU64(vec![element]))
+}
+
+fn i64_value(element: i64) -> Value {
+    int_value(element).unwrap_or_else(|_| Value::LSTI64(vec![element]))
+}
+
+pub(crate) fn string_value(bytes: &[u8]) -> TsonResult<Value> {
+    String::from_utf8(bytes.to_vec()).map(Value::STR).map_err(|_| TsonError::new("utf8 : bad string"))
+}
+
+/// None when `index` is out of bounds.
+pub(crate) fn get_element(target: &Value, index: usize) -> Option<TsonResult<Value>> {
+    match *target {
+        Value::LSTU8(ref v) => v.get(index).map(|e| int_value(*e)),
+        Value::LSTI8(ref v) => v.get(index).map(|e| int_value(*e)),
+        Value::LSTU16(ref v) => v.get(index).map(|e| int_value(*e)),
+        Value::LSTI16(ref v) => v.get(index).map(|e| int_value(*e)),
+        Value::LSTU32(ref v) => v.get(index).map(|e| int_value(*e)),
+        Value::LSTI32(ref v) => v.get(index).map(|e| int_value(*e)),
+        Value::LSTU64(ref v) => v.get(index).map(|e| Ok(u64_value(*e))),
+        Value::LSTI64(ref v) => v.get(index).map(|e| Ok(i64_value(*e))),
+        Value::LSTF32(ref v) => v.get(index).map(|e| Ok(Value::F64(f64::from(*e)))),
+        Value::LSTF64(ref v) => v.get(index).map(|e| Ok(Value::F64(*e))),
+        Value::LSTSTR(ref v) => match v.build_starts() {
+            Ok(starts) if index + 1 < starts.len() => Some(string_value(&v.bytes[starts[index]..starts[index + 1] - 1])),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        },
+        _ => None,
+    }
+}
+
+fn remove_element(target: &mut Value, index: usize) -> Option<TsonResult<Value>> {
+    fn remove<T>(v: &mut Vec<T>, index: usize) -> Option<T> {
+        if index < v.len() { Some(v.remove(index)) } else { None }
+    }
+    match *target {
+        Value::LSTU8(ref mut v) => remove(v, index).map(int_value),
+        Value::LSTI8(ref mut v) => remove(v, index).map(int_value),
+        Value::LSTU16(ref mut v) => remove(v, index).map(int_value),
+        Value::LSTI16(ref mut v) => remove(v, index).map(int_value),
+        Value::LSTU32(ref mut v) => remove(v, index).map(int_value),
+        Value::LSTI32(ref mut v) => remove(v, index).map(int_value),
+        Value::LSTU64(ref mut v) => remove(v, index).map(|e| Ok(u64_value(e))),
+        Value::LSTI64(ref mut v) => remove(v, index).map(|e| Ok(i64_value(e))),
+        Value::LSTF32(ref mut v) => remove(v, index).map(|e| Ok(Value::F64(f64::from(e)))),
+        Value::LSTF64(ref mut v) => remove(v, index).map(|e| Ok(Value::F64(e))),
+        Value::LSTSTR(ref mut v) => match v.build_starts() {
+            Ok(starts) if index + 1 < starts.len() => {
+                let bytes: Vec<u8> = v.bytes.drain(starts[index]..starts[index + 1]).collect();
+                Some(string_value(&bytes[..bytes.len() - 1]))
+            }
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        },
+        _ => None,
+    }
+}
+
+fn set_element(target: &mut Value, index: usize, value: Value) -> TsonResult<()> {
+    fn set<T>(v: &mut Vec<T>, index: usize, element: T) -> TsonResult<()> {
+        if index < v.len() {
+            v[index] = element;
+        } else if index == v.len() {
+            v.push(element);
+        } else {
+            return Err(TsonError::new(format!("index {} out of bounds -- length {}", index, v.len())));
+        }
+        Ok(())
+    }
+    fn int<T>(value: &Value) -> TsonResult<T> where T: TryFrom<i128> {
+        let element = match *value {
+            Value::I32(v) => i128::from(v),
+            Value::F64(v) if v.fract() == 0.0 => v as i128,
+            _ => return Err(TsonError::new(format!("integer expected -- found {}", value.tson_type()))),
+        };
+        T::try_from(element).map_err(|_| TsonError::new(format!("{} out of range", element)))
+    }
+    fn float(value: &Value) -> TsonResult<f64> {
+        match *value {
+            Value::I32(v) => Ok(f64::from(v)),
+            Value::F64(v) => Ok(v),
+            _ => Err(TsonError::new(format!("number expected -- found {}", value.tson_type()))),
+        }
+    }
+    fn float32(value: &Value) -> TsonResult<f32> {
+        let element = float(value)?;
+        match element as f32 {
+            v if f64::from(v) == element || element.is_nan() => Ok(v),
+            _ => Err(TsonError::new(format!("{} loses precision", element))),
+        }
+    }
+    match *target {
+        Value::LSTU8(ref mut v) => set(v, index, int(&value)?),
+        Value::LSTI8(ref mut v) => set(v, index, int(&value)?),
+        Value::LSTU16(ref mut v) => set(v, index, int(&value)?),
+        Value::LSTI16(ref mut v) => set(v, index, int(&value)?),
+        Value::LSTU32(ref mut v) => set(v, index, int(&value)?),
+        Value::LSTI32(ref mut v) => set(v, index, int(&value)?),
+        Value::LSTU64(ref mut v) => set(v, index, int(&value)?),
+        Value::LSTI64(ref mut v) => set(v, index, int(&value)?),
+        Value::LSTF32(ref mut v) => set(v, index, float32(&value)?),
+        Value::LSTF64(ref mut v) => set(v, index, float(&value)?),
+        Value::LSTSTR(ref mut v) => {
+            let mut bytes = match value {
+                Value::STR(ref s) if !s.contains('\0') => s.clone().into_bytes(),
+                Value::STR(_) => return Err(TsonError::new("string containing NUL")),
+                ref other => return Err(other.type_error(TsonType::STR)),
+            };
+            bytes.push(0);
+            let starts = v.build_starts()?;
+            let len = starts.len() - 1;
+            let range = if index < len {
+                starts[index]..starts[index + 1]
+            } else if index == len {
+                v.bytes.len()..v.bytes.len()
+            } else {
+                return Err(TsonError::new(format!("index {} out of bounds -- length {}", index, len)));
+            };
+            v.bytes.splice(range, bytes);
+            Ok(())
+        }
+        _ => Err(TsonError::new(format!("cannot set an element of {}", target.tson_type()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Value;
+
+    #[test]
+    fn pointer() {
+        let doc = tson!({"columns": [{"name": "a", "values": f64[1.0, 2.5]}, {"values": str["x", "y"]}], "a/b": {"~": u64[1 << 40, 3]}});
+        assert_eq!(*doc.pointer("/columns/0/name").unwrap(), tson!("a"));
+        assert_eq!(*doc.pointer("/columns/0/values/1").unwrap(), tson!(2.5));
+        assert_eq!(*doc.pointer("/columns/1/values/1").unwrap(), tson!("y"));
+        assert_eq!(*doc.pointer("/a~1b/~0/0").unwrap(), Value::F64((1u64 << 40) as f64));
+        assert_eq!(*doc.pointer("/a~1b/~0/1").unwrap(), tson!(3));
+        assert_eq!(*doc.pointer("").unwrap(), doc);
+
+        let mut large = tson!({"u": u64[u64::MAX, 1], "i": i64[i64::MIN + 1]});
+        assert_eq!(*large.pointer("/u/0").unwrap(), tson!(u64[u64::MAX]));
+        assert_eq!(*large.pointer("/i/0").unwrap(), tson!(i64[i64::MIN + 1]));
+        assert_eq!(large.remove_at("/u/0").unwrap(), tson!(u64[u64::MAX]));
+
+        assert_eq!(doc.pointer("/columns/2/name").unwrap_err().to_string(), "/columns/2 -- not found");
+        assert_eq!(doc.pointer("/columns/0/values/2").unwrap_err().to_string(), "/columns/0/values/2 -- not found");
+        assert_eq!(doc.pointer("/columns/0/name/x").unwrap_err().to_string(), "/columns/0/name/x -- cannot index STR");
+        assert_eq!(doc.pointer("/columns/0/values/x").unwrap_err().to_string(), "/columns/0/values/x -- index expected");
+        assert!(doc.pointer("columns").is_err());
+    }
+
+    #[test]
+    fn leading_zeros() {
+        let mut doc = tson!({"01": "a", "1": "b", "list": [1, 2]});
+        assert_eq!(*doc.pointer("/01").unwrap(), tson!("a"));
+        assert_eq!(*doc.pointer("/1").unwrap(), tson!("b"));
+        assert!(doc.pointer("/list/01").is_err());
+        doc.set_at("/01", tson!("c")).unwrap();
+        assert_eq!(doc.remove_at("/1").unwrap(), tson!("b"));
+        assert_eq!(doc, tson!({"01": "c", "list": [1, 2]}));
+    }
+
+    #[test]
+    fn set_and_remove() {
+        let mut doc = tson!({"list": [1], "values": u8[1, 2], "levels": str["a", "b"]});
+        doc.set_at("/new/nested/key", tson!(true)).unwrap();
+        doc.set_at("/list/-", tson!(2)).unwrap();
+        doc.set_at("/list/0", tson!("one")).unwrap();
+        doc.set_at("/values/2", tson!(3)).unwrap();
+        doc.set_at("/levels/0", tson!("c")).unwrap();
+        *doc.pointer_mut("/list/1").unwrap() = tson!(3);
+        assert_eq!(doc, tson!({"list": ["one", 3], "values": u8[1, 2, 3], "levels": str["c", "b"], "new": {"nested": {"key": true}}}));
+
+        assert_eq!(doc.set_at("/values/0", tson!(256)).unwrap_err().to_string(), "/values/0 -- 256 out of range");
+        let mut floats = tson!(f32[1.0]);
+        floats.set_at("/0", tson!(0.5)).unwrap();
+        assert_eq!(floats.set_at("/1", tson!(0.1)).unwrap_err().to_string(), "/1 -- 0.1 loses precision");
+        assert_eq!(floats, tson!(f32[0.5]));
+        assert_eq!(doc.set_at("/list/0/x", tson!(1)).unwrap_err().to_string(), "/list/0/x -- cannot index STR");
+        assert!(doc.pointer_mut("/values/0").is_err());
+
+        assert_eq!(doc.remove_at("/values/0").unwrap(), tson!(1));
+        assert_eq!(doc.remove_at("/levels/1").unwrap(), tson!("b"));
+        assert_eq!(doc.remove_at("/new/nested").unwrap(), tson!({"key": true}));
+        assert_eq!(doc.remove_at("/list/0").unwrap(), tson!("one"));
+        assert_eq!(doc.remove_at("/list/5").unwrap_err().to_string(), "/list/5 -- not found");
+        assert_eq!(doc, tson!({"list": [3], "values": u8[2, 3], "levels": str["c"], "new": {}}));
+    }
+}
@@ -0,0 +1,513 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use ::{sorted_keys, Map, StrVec, TsonError, TsonResult, Value};
+use pointer::get_element;
+
+/// A compiled query, JSONPath like, to evaluate many times.
+///
+/// ```text
+/// $.columns[*].name           map key, all elements or map values
+/// $..values                   key at any depth
+/// $.columns[0].values[10:20]  list element, slice
+/// $.rows[?(@.count >= 2)]     elements matching a filter on scalar fields
+/// ```
+///
+/// Elements of typed lists and LSTSTR are given as owned scalars like `Value::pointer` does,
+/// and a slice of a typed list as one typed list of the same type. Wildcards and descendants
+/// walk map values in key order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Key(String),
+    /// Negative indices count from the end.
+    Index(i64),
+    Wildcard,
+    /// The value and all its descendants.
+    Descendants,
+    Slice(Option<i64>, Option<i64>),
+    Filter(Filter),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Filter {
+    field: Vec<Step>,
+    /// None tests that the field exists and is not NULL, a missing field never matching.
+    condition: Option<(Comparison, Value)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Query {
+    pub fn compile(query: &str) -> TsonResult<Query> {
+        let mut parser = Parser { chars: query.chars().collect(), position: 0 };
+        parser.eat('$');
+        let steps = parser.steps(false)?;
+        if parser.position < parser.chars.len() {
+            return Err(parser.error("unexpected character"));
+        }
+        Ok(Query { steps })
+    }
+
+    /// Matching values, borrowed from `value` unless made of typed list elements.
+    pub fn select<'a>(&self, value: &'a Value) -> TsonResult<Vec<Cow<'a, Value>>> {
+        let mut nodes = vec![Cow::Borrowed(value)];
+        for step in self.steps.iter() {
+            let mut next = Vec::new();
+            for node in nodes {
+                apply_cow(step, node, &mut next)?;
+            }
+            nodes = next;
+        }
+        Ok(nodes)
+    }
+
+    pub fn select_cloned(&self, value: &Value) -> TsonResult<Vec<Value>> {
+        Ok(self.select(value)?.into_iter().map(Cow::into_owned).collect())
+    }
+}
+
+impl FromStr for Query {
+    type Err = TsonError;
+
+    fn from_str(query: &str) -> TsonResult<Query> {
+        Query::compile(query)
+    }
+}
+
+impl Value {
+    /// Compiles and evaluates `query`, see `Query`.
+    pub fn query(&self, query: &str) -> TsonResult<Vec<Cow<'_, Value>>> {
+        Query::compile(query)?.select(self)
+    }
+}
+
+/// Like `apply`, a node made of typed list elements having no children to borrow.
+fn apply_cow<'a>(step: &Step, node: Cow<'a, Value>, out: &mut Vec<Cow<'a, Value>>) -> TsonResult<()> {
+    match node {
+        Cow::Borrowed(node) => apply(step, node, out),
+        Cow::Owned(node) => {
+            if let Step::Descendants = *step {
+                out.push(Cow::Owned(node));
+                return Ok(());
+            }
+            let mut owned = Vec::new();
+            apply(step, &node, &mut owned)?;
+            out.extend(owned.into_iter().map(|v| Cow::Owned(v.into_owned())));
+            Ok(())
+        }
+    }
+}
+
+fn apply<'a>(step: &Step, value: &'a Value, out: &mut Vec<Cow<'a, Value>>) -> TsonResult<()> {
+    match *step {
+        Step::Key(ref key) => {
+            if let Some(child) = value.get(key) {
+                out.push(Cow::Borrowed(child));
+            }
+        }
+        Step::Index(index) => {
+            if let Some(index) = list_len(value).and_then(|len| resolve_index(index, len)) {
+                push_element(value, index, out)?;
+            }
+        }
+        Step::Wildcard => children(value, out)?,
+        Step::Descendants => {
+            // Depth first with an explicit stack, children in order.
+            let mut stack = vec![value];
+            while let Some(value) = stack.pop() {
+                out.push(Cow::Borrowed(value));
+                let start = stack.len();
+                match *value {
+                    Value::MAP(ref map) => stack.extend(map_values(map)),
+                    Value::LST(ref list) => stack.extend(list.iter()),
+                    _ => {}
+                }
+                stack[start..].reverse();
+            }
+        }
+        Step::Slice(start, end) => {
+            let len = match list_len(value) {
+                Some(len) => len,
+                None => return Ok(()),
+            };
+            let bound = |bound: i64| if bound < 0 { (len as i64 + bound).max(0) as usize } else { (bound as usize).min(len) };
+            let start = start.map_or(0, bound);
+            let end = end.map_or(len, bound).max(start);
+            match *value {
+                Value::LST(ref list) => out.extend(list[start..end].iter().map(Cow::Borrowed)),
+                _ => out.push(Cow::Owned(slice_typed(value, start, end)?)),
+            }
+        }
+        Step::Filter(ref filter) => {
+            let mut candidates = Vec::new();
+            children(value, &mut candidates)?;
+            for candidate in candidates {
+                if filter.matches(&candidate)? {
+                    out.push(candidate);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn children<'a>(value: &'a Value, out: &mut Vec<Cow<'a, Value>>) -> TsonResult<()> {
+    match *value {
+        Value::MAP(ref map) => out.extend(map_values(map).map(Cow::Borrowed)),
+        Value::LST(ref list) => out.extend(list.iter().map(Cow::Borrowed)),
+        _ => {
+            for index in 0..list_len(value).unwrap_or(0) {
+                push_element(value, index, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn map_values(map: &Map<String, Value>) -> impl Iterator<Item = &Value> {
+    sorted_keys(map, map).into_iter().map(move |key| &map[key])
+}
+
+fn push_element<'a>(value: &'a Value, index: usize, out: &mut Vec<Cow<'a, Value>>) -> TsonResult<()> {
+    match *value {
+        Value::LST(ref list) => out.push(Cow::Borrowed(&list[index])),
+        _ => {
+            if let Some(element) = get_element(value, index) {
+                out.push(Cow::Owned(element?));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    if index >= 0 && (index as usize) < len { Some(index as usize) } else { None }
+}
+
+fn list_len(value: &Value) -> Option<usize> {
+    match *value {
+        Value::LST(ref v) => Some(v.len()),
+        Value::LSTU8(ref v) => Some(v.len()),
+        Value::LSTI8(ref v) => Some(v.len()),
+        Value::LSTU16(ref v) => Some(v.len()),
+        Value::LSTI16(ref v) => Some(v.len()),
+        Value::LSTU32(ref v) => Some(v.len()),
+        Value::LSTI32(ref v) => Some(v.len()),
+        Value::LSTU64(ref v) => Some(v.len()),
+        Value::LSTI64(ref v) => Some(v.len()),
+        Value::LSTF32(ref v) => Some(v.len()),
+        Value::LSTF64(ref v) => Some(v.len()),
+        Value::LSTSTR(ref v) => Some(v.bytes.iter().filter(|b| **b == 0).count()),
+        _ => None,
+    }
+}
+
+fn slice_typed(value: &Value, start: usize, end: usize) -> TsonResult<Value> {
+    Ok(match *value {
+        Value::LSTU8(ref v) => Value::LSTU8(v[start..end].to_vec()),
+        Value::LSTI8(ref v) => Value::LSTI8(v[start..end].to_vec()),
+        Value::LSTU16(ref v) => Value::LSTU16(v[start..end].to_vec()),
+        Value::LSTI16(ref v) => Value::LSTI16(v[start..end].to_vec()),
+        Value::LSTU32(ref v) => Value::LSTU32(v[start..end].to_vec()),
+        Value::LSTI32(ref v) => Value::LSTI32(v[start..end].to_vec()),
+        Value::LSTU64(ref v) => Value::LSTU64(v[start..end].to_vec()),
+        Value::LSTI64(ref v) => Value::LSTI64(v[start..end].to_vec()),
+        Value::LSTF32(ref v) => Value::LSTF32(v[start..end].to_vec()),
+        Value::LSTF64(ref v) => Value::LSTF64(v[start..end].to_vec()),
+        Value::LSTSTR(ref v) => {
+            let starts = v.build_starts()?;
+            Value::LSTSTR(StrVec::from_bytes_unchecked(v.bytes[starts[start]..starts[end]].to_vec()))
+        }
+        _ => unreachable!(),
+    })
+}
+
+impl Filter {
+    fn matches(&self, candidate: &Value) -> TsonResult<bool> {
+        let mut nodes = vec![Cow::Borrowed(candidate)];
+        for step in self.field.iter() {
+            let mut next = Vec::new();
+            if let Some(node) = nodes.into_iter().next() {
+                apply_cow(step, node, &mut next)?;
+            }
+            nodes = next;
+        }
+        let field = match nodes.first() {
+            Some(field) => field,
+            None => return Ok(false),
+        };
+        let (comparison, literal) = match self.condition {
+            Some((comparison, ref literal)) => (comparison, literal),
+            None => return Ok(!field.is_null()),
+        };
+        let ordering = match (&**field, literal) {
+            (Value::STR(a), Value::STR(b)) => Some(a.cmp(b)),
+            (Value::BOOL(a), Value::BOOL(b)) => Some(a.cmp(b)),
+            (Value::NULL, Value::NULL) => Some(Ordering::Equal),
+            (a, b) => match (number(a), number(b)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => None,
+            },
+        };
+        Ok(match (comparison, ordering) {
+            (Comparison::Ne, ordering) => ordering != Some(Ordering::Equal),
+            (_, None) => false,
+            (Comparison::Eq, Some(ordering)) => ordering == Ordering::Equal,
+            (Comparison::Lt, Some(ordering)) => ordering == Ordering::Less,
+            (Comparison::Le, Some(ordering)) => ordering != Ordering::Greater,
+            (Comparison::Gt, Some(ordering)) => ordering == Ordering::Greater,
+            (Comparison::Ge, Some(ordering)) => ordering != Ordering::Less,
+        })
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match *value {
+        Value::I32(v) => Some(f64::from(v)),
+        Value::F64(v) => Some(v),
+        _ => None,
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> TsonError {
+        let query: String = self.chars.iter().collect();
+        TsonError::new(format!("wrong format -- query {:?} -- {} at {}", query, message, self.position))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> TsonResult<()> {
+        if self.eat(c) { Ok(()) } else { Err(self.error(&format!("{:?} expected", c))) }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    /// Steps up to the end, or up to the end of a filter field.
+    fn steps(&mut self, field: bool) -> TsonResult<Vec<Step>> {
+        let mut steps = Vec::new();
+        loop {
+            match self.peek() {
+                Some('.') => {
+                    self.position += 1;
+                    if !field && self.eat('.') {
+                        steps.push(Step::Descendants);
+                        if self.peek() == Some('[') {
+                            continue;
+                        }
+                    }
+                    if self.eat('*') {
+                        steps.push(Step::Wildcard);
+                    } else {
+                        steps.push(Step::Key(self.name()?));
+                    }
+                }
+                Some('[') => {
+                    self.position += 1;
+                    self.skip_spaces();
+                    steps.push(self.bracket(field)?);
+                    self.skip_spaces();
+                    self.expect(']')?;
+                }
+                _ => return Ok(steps),
+            }
+        }
+    }
+
+    fn name(&mut self) -> TsonResult<String> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Err(self.error("name expected"));
+        }
+        Ok(self.chars[start..self.position].iter().collect())
+    }
+
+    fn bracket(&mut self, field: bool) -> TsonResult<Step> {
+        match self.peek() {
+            Some('*') if !field => {
+                self.position += 1;
+                Ok(Step::Wildcard)
+            }
+            Some('"') | Some('\'') => Ok(Step::Key(self.string()?)),
+            Some('?') if !field => {
+                self.position += 1;
+                self.expect('(')?;
+                self.skip_spaces();
+                let filter = self.filter()?;
+                self.skip_spaces();
+                self.expect(')')?;
+                Ok(Step::Filter(filter))
+            }
+            _ => {
+                let start = self.integer()?;
+                self.skip_spaces();
+                if field || !self.eat(':') {
+                    return start.map(Step::Index).ok_or_else(|| self.error("index expected"));
+                }
+                self.skip_spaces();
+                Ok(Step::Slice(start, self.integer()?))
+            }
+        }
+    }
+
+    fn integer(&mut self) -> TsonResult<Option<i64>> {
+        let start = self.position;
+        self.eat('-');
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Ok(None);
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse().map(Some).map_err(|_| self.error("integer expected"))
+    }
+
+    fn string(&mut self) -> TsonResult<String> {
+        let quote = self.peek().expect("quote");
+        self.position += 1;
+        let mut string = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => {
+                    self.position += 1;
+                    return Ok(string);
+                }
+                Some('\\') => {
+                    self.position += 1;
+                    string.push(self.peek().ok_or_else(|| self.error("unterminated string"))?);
+                    self.position += 1;
+                }
+                Some(c) => {
+                    string.push(c);
+                    self.position += 1;
+                }
+            }
+        }
+    }
+
+    fn filter(&mut self) -> TsonResult<Filter> {
+        self.expect('@')?;
+        let field = self.steps(true)?;
+        self.skip_spaces();
+        let comparison = match (self.peek(), self.chars.get(self.position + 1).cloned()) {
+            (Some('='), Some('=')) => Comparison::Eq,
+            (Some('!'), Some('=')) => Comparison::Ne,
+            (Some('<'), Some('=')) => Comparison::Le,
+            (Some('>'), Some('=')) => Comparison::Ge,
+            (Some('<'), _) => Comparison::Lt,
+            (Some('>'), _) => Comparison::Gt,
+            _ => return Ok(Filter { field, condition: None }),
+        };
+        self.position += match comparison {
+            Comparison::Lt | Comparison::Gt => 1,
+            _ => 2,
+        };
+        self.skip_spaces();
+        let literal = self.literal()?;
+        Ok(Filter { field, condition: Some((comparison, literal)) })
+    }
+
+    fn literal(&mut self) -> TsonResult<Value> {
+        match self.peek() {
+            Some('"') | Some('\'') => return Ok(Value::STR(self.string()?)),
+            Some(c) if c.is_alphabetic() => {
+                return match self.name()?.as_str() {
+                    "true" => Ok(Value::BOOL(true)),
+                    "false" => Ok(Value::BOOL(false)),
+                    "null" => Ok(Value::NULL),
+                    _ => Err(self.error("literal expected")),
+                };
+            }
+            _ => {}
+        }
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.position += 1;
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        if let Ok(v) = text.parse::<i32>() {
+            return Ok(Value::I32(v));
+        }
+        text.parse::<f64>().map(Value::F64).map_err(|_| self.error("literal expected"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use query::Query;
+    use TsonResult;
+
+    #[test]
+    fn query() -> TsonResult<()> {
+        let doc = tson!({
+            "columns": [
+                {"name": "a", "values": f64[1.0, 2.0, 3.0, 4.0]},
+                {"name": "b", "values": str["x", "y", "z"], "meta": {"name": "nested"}}
+            ],
+            "rows": [{"count": 1, "tag": "x"}, {"count": 3}, {"count": 2.5, "tag": "y"}]
+        });
+
+        let names = Query::compile("$.columns[*].name")?;
+        assert_eq!(names.select_cloned(&doc)?, vec![tson!("a"), tson!("b")]);
+        assert_eq!(Query::compile("$..name")?.select_cloned(&doc)?, vec![tson!("a"), tson!("b"), tson!("nested")]);
+        assert_eq!(doc.query("$.columns[1].meta")?, vec![Cow::Borrowed(&doc["columns"][1]["meta"])]);
+        assert!(doc.query("$..values")?.iter().all(|v| matches!(*v, Cow::Borrowed(_))));
+        assert_eq!(*doc.query("$.columns[-1]['values'][1]")?[0], tson!("y"));
+        assert_eq!(*doc.query("$.columns[0].values[1:-1]")?[0], tson!(f64[2.0, 3.0]));
+        assert_eq!(*doc.query("$.columns[1].values[:2]")?[0], tson!(str["x", "y"]));
+        assert_eq!(doc.query("$.rows[1:]")?.len(), 2);
+        assert_eq!(Query::compile("$.columns[0].values[?(@ >= 3)]")?.select_cloned(&doc)?, vec![tson!(3.0), tson!(4.0)]);
+
+        let counts = Query::compile("$.rows[?(@.count > 1)].count")?;
+        assert_eq!(counts.select_cloned(&doc)?, vec![tson!(3), tson!(2.5)]);
+        assert_eq!(doc.query("$.rows[?(@.tag)]")?.len(), 2);
+        assert_eq!(doc.query("$.rows[?(@.tag != 'x')]")?.len(), 1);
+        assert_eq!(doc.query("$.rows[?(@['tag'] == \"y\")].count")?[0].as_f64()?, 2.5);
+        assert!(doc.query("$.missing[0]")?.is_empty());
+
+        assert!(Query::compile("$.rows[?(@.count >)]").is_err());
+        assert_eq!(Query::compile("$.rows[").unwrap_err().to_string(), r#"wrong format -- query "$.rows[" -- index expected at 7"#);
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@ extern crate bytes;
 use super::*;
 
 use bytes::{BufMut};
+use sha2::Digest;
 use std::slice;
 //use std::mem::size_of;
 
@@ -78,6 +79,12 @@ impl CountWriter {
     }
 }
 
+impl Default for CountWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Writer for CountWriter {
     fn add_u8(&mut self, _value: u8) -> TsonResult<()> {
         self.size += 1;
@@ -126,10 +133,92 @@ impl Writer for CountWriter {
     }
 }
 
-pub struct Serializer {}
+/// Feeds the encoded bytes to a hasher instead of storing them.
+pub struct DigestWriter<D> {
+    pub digest: D,
+}
+
+impl<D> DigestWriter<D> where D: Digest {
+    pub fn new() -> DigestWriter<D> {
+        DigestWriter { digest: D::new() }
+    }
+
+    pub fn finalize(self) -> Vec<u8> {
+        self.digest.finalize().to_vec()
+    }
+}
+
+impl<D> Default for DigestWriter<D> where D: Digest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> Writer for DigestWriter<D> where D: Digest {
+    fn add_u8(&mut self, value: u8) -> TsonResult<()> {
+        self.digest.update([value]);
+        Ok(())
+    }
+    fn add_i8(&mut self, value: i8) -> TsonResult<()> {
+        self.digest.update(value.to_le_bytes());
+        Ok(())
+    }
+    fn add_u32(&mut self, value: u32) -> TsonResult<()> {
+        self.digest.update(value.to_le_bytes());
+        Ok(())
+    }
+    fn add_i32(&mut self, value: i32) -> TsonResult<()> {
+        self.digest.update(value.to_le_bytes());
+        Ok(())
+    }
+    fn add_f64(&mut self, value: f64) -> TsonResult<()> {
+        self.digest.update(value.to_le_bytes());
+        Ok(())
+    }
+    fn add_u16(&mut self, value: u16) -> TsonResult<()> {
+        self.digest.update(value.to_le_bytes());
+        Ok(())
+    }
+    fn add_i16(&mut self, value: i16) -> TsonResult<()> {
+        self.digest.update(value.to_le_bytes());
+        Ok(())
+    }
+    fn add_u64(&mut self, value: u64) -> TsonResult<()> {
+        self.digest.update(value.to_le_bytes());
+        Ok(())
+    }
+    fn add_i64(&mut self, value: i64) -> TsonResult<()> {
+        self.digest.update(value.to_le_bytes());
+        Ok(())
+    }
+    fn add_f32(&mut self, value: f32) -> TsonResult<()> {
+        self.digest.update(value.to_le_bytes());
+        Ok(())
+    }
+
+    fn put_slice(&mut self, src: &[u8]) -> TsonResult<()> {
+        self.digest.update(src);
+        Ok(())
+    }
+}
+
+/// In canonical mode the same `Value` always gives the same bytes :
+/// map keys are sorted by their utf8 bytes, every NaN is written as the
+/// default quiet NaN and -0.0 is written as 0.0.
+pub struct Serializer {
+    canonical: bool,
+}
+
+impl Default for Serializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Serializer {
-    pub fn new() -> Serializer { Serializer {} }
+    pub fn new() -> Serializer { Serializer { canonical: false } }
+
+    pub fn canonical() -> Serializer { Serializer { canonical: true } }
 
     pub fn encoded_size(&self, value: &Value) -> TsonResult<usize> {
         let mut buf = CountWriter::new();
@@ -161,21 +250,25 @@ impl Serializer {
     fn add_object(&self, value: &Value, buf: &mut dyn Writer) -> TsonResult<()> {
         match *value {
             Value::NULL => {
-                buf.add_u8(NULL_TYPE)?;
+                buf.add_u8(TsonType::NULL.code())?;
             }
             Value::STR(ref v) => {
                 self.add_string(buf, v)?;
             }
             Value::I32(v) => {
-                buf.add_u8(INTEGER_TYPE)?;
+                buf.add_u8(TsonType::I32.code())?;
                 buf.add_i32(v)?;
             }
             Value::F64(v) => {
-                buf.add_u8(DOUBLE_TYPE)?;
-                buf.add_f64(v)?;
+                buf.add_u8(TsonType::F64.code())?;
+                if self.canonical {
+                    buf.add_f64(canonical_f64(v))?;
+                } else {
+                    buf.add_f64(v)?;
+                }
             }
             Value::BOOL(v) => {
-                buf.add_u8(BOOL_TYPE)?;
+                buf.add_u8(TsonType::BOOL.code())?;
                 if v {
                     buf.add_u8(1)?;
                 } else {
@@ -183,109 +276,61 @@ impl Serializer {
                 }
             }
             Value::LST(ref v) => {
-                buf.add_u8(LIST_TYPE)?;
+                buf.add_u8(TsonType::LST.code())?;
                 self.add_len(buf, v.len())?;
                 for object in v.iter() {
                     self.add_object(object, buf)?;
                 }
             }
             Value::MAP(ref v) => {
-                buf.add_u8(MAP_TYPE)?;
+                buf.add_u8(TsonType::MAP.code())?;
                 self.add_len(buf, v.len())?;
-                for (k, v) in v.iter() {
-                    self.add_string(buf, k)?;
-                    self.add_object(v, buf)?;
+                if self.canonical {
+                    let mut entries: Vec<(&String, &Value)> = v.iter().collect();
+                    entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                    for (k, v) in entries {
+                        self.add_string(buf, k)?;
+                        self.add_object(v, buf)?;
+                    }
+                } else {
+                    for (k, v) in v.iter() {
+                        self.add_string(buf, k)?;
+                        self.add_object(v, buf)?;
+                    }
                 }
             }
-            Value::LSTU8(ref v) => {
-                buf.add_u8(LIST_UINT8_TYPE)?;
-                self.add_len(buf, v.len())?;
-                buf.put_slice(&v)?;
-                // for i in v.iter() {
-                //     buf.add_u8(*i)?;
-                // }
-            }
-            Value::LSTI8(ref v) => {
-                buf.add_u8(LIST_INT8_TYPE)?;
-                self.add_len(buf, v.len())?;
-                let slice = unsafe { slice::from_raw_parts(v.as_ptr() as *mut u8, v.len() * std::mem::size_of::<i8>()) };
-                buf.put_slice(slice)?;
-                // for i in v.iter() {
-                //     buf.add_i8(*i)?;
-                // }
-            }
-            Value::LSTU16(ref v) => {
-                buf.add_u8(LIST_UINT16_TYPE)?;
-                self.add_len(buf, v.len())?;
-                let slice = unsafe { slice::from_raw_parts(v.as_ptr() as *mut u8, v.len() * std::mem::size_of::<u16>()) };
-                buf.put_slice(slice)?;
-                // for i in v.iter() {
-                //     buf.add_u16(*i)?;
-                // }
-            }
-            Value::LSTI16(ref v) => {
-                buf.add_u8(LIST_INT16_TYPE)?;
-                self.add_len(buf, v.len())?;
-                let slice = unsafe { slice::from_raw_parts(v.as_ptr() as *mut u8, v.len() * std::mem::size_of::<i16>()) };
-                buf.put_slice(slice)?;
-                // for i in v.iter() {
-                //     buf.add_i16(*i)?;
-                // }
-            }
-            Value::LSTU32(ref v) => {
-                buf.add_u8(LIST_UINT32_TYPE)?;
-                self.add_len(buf, v.len())?;
-                let slice = unsafe { slice::from_raw_parts(v.as_ptr() as *mut u8, v.len() * std::mem::size_of::<u32>()) };
-                buf.put_slice(slice)?;
-                // for i in v.iter() {
-                //     buf.add_u32(*i)?;
-                // }
-            }
-            Value::LSTI32(ref v) => {
-                buf.add_u8(LIST_INT32_TYPE)?;
-                self.add_len(buf, v.len())?;
-                let slice = unsafe { slice::from_raw_parts(v.as_ptr() as *mut u8, v.len() * std::mem::size_of::<i32>()) };
-                buf.put_slice(slice)?;
-                // for i in v.iter() {
-                //     buf.add_i32(*i)?;
-                // }
-            }
+            Value::LSTU8(ref v) => self.add_typed_list(buf, v)?,
+            Value::LSTI8(ref v) => self.add_typed_list(buf, v)?,
+            Value::LSTU16(ref v) => self.add_typed_list(buf, v)?,
+            Value::LSTI16(ref v) => self.add_typed_list(buf, v)?,
+            Value::LSTU32(ref v) => self.add_typed_list(buf, v)?,
+            Value::LSTI32(ref v) => self.add_typed_list(buf, v)?,
             Value::LSTU64(ref v) => {
-                buf.add_u8(LIST_UINT64_TYPE)?;
+                buf.add_u8(TsonType::LSTU64.code())?;
                 self.add_len(buf, v.len())?;
                 for i in v.iter() {
                     buf.add_u64(*i)?;
                 }
             }
-            Value::LSTI64(ref v) => {
-                buf.add_u8(LIST_INT64_TYPE)?;
+            Value::LSTI64(ref v) => self.add_typed_list(buf, v)?,
+            Value::LSTF32(ref v) if self.canonical => {
+                buf.add_u8(TsonType::LSTF32.code())?;
                 self.add_len(buf, v.len())?;
-                let slice = unsafe { slice::from_raw_parts(v.as_ptr() as *mut u8, v.len() * std::mem::size_of::<i64>()) };
-                buf.put_slice(slice)?;
-                // for i in v.iter() {
-                //     buf.add_i64(*i)?;
-                // }
-            }
-            Value::LSTF32(ref v) => {
-                buf.add_u8(LIST_FLOAT32_TYPE)?;
-                self.add_len(buf, v.len())?;
-                let slice = unsafe { slice::from_raw_parts(v.as_ptr() as *mut u8, v.len() * std::mem::size_of::<f32>()) };
-                buf.put_slice(slice)?;
-                // for i in v.iter() {
-                //     buf.add_f32(*i)?;
-                // }
+                for i in v.iter() {
+                    buf.add_f32(canonical_f32(*i))?;
+                }
             }
-            Value::LSTF64(ref v) => {
-                buf.add_u8(LIST_FLOAT64_TYPE)?;
+            Value::LSTF32(ref v) => self.add_typed_list(buf, v)?,
+            Value::LSTF64(ref v) if self.canonical => {
+                buf.add_u8(TsonType::LSTF64.code())?;
                 self.add_len(buf, v.len())?;
-                let slice = unsafe { slice::from_raw_parts(v.as_ptr() as *mut u8, v.len() * std::mem::size_of::<f64>()) };
-                buf.put_slice(slice)?;
-                // for i in v.iter() {
-                //     buf.add_f64(*i)?;
-                // }
+                for i in v.iter() {
+                    buf.add_f64(canonical_f64(*i))?;
+                }
             }
+            Value::LSTF64(ref v) => self.add_typed_list(buf, v)?,
             Value::LSTSTR(ref v) => {
-                buf.add_u8(LIST_STRING_TYPE)?;
+                buf.add_u8(TsonType::LSTSTR.code())?;
                 let len_in_bytes = v.bytes.len();
                 self.add_len(buf, len_in_bytes)?;
                 buf.put_slice(&v.bytes[..])?;
@@ -299,6 +344,14 @@ impl Serializer {
     }
 
 
+    /// Writes a typed list of any element type, elements are written as they lie in memory (little endian).
+    pub fn add_typed_list<T>(&self, buf: &mut dyn Writer, v: &[T]) -> TsonResult<()> where T: TsonTypedListType {
+        buf.add_u8(T::tson_type().code())?;
+        self.add_len(buf, v.len())?;
+        let slice = unsafe { slice::from_raw_parts(v.as_ptr() as *const u8, std::mem::size_of_val(v)) };
+        buf.put_slice(slice)
+    }
+
     fn add_len(&self, buf: &mut dyn Writer, len: usize) -> TsonResult<()> {
         if len > MAX_LIST_LENGTH {
             return Err(TsonError::new("list too large"));
@@ -307,7 +360,7 @@ impl Serializer {
     }
 
     fn add_string(&self, buf: &mut dyn Writer, value: &str) -> TsonResult<()> {
-        buf.add_u8(STRING_TYPE)?;
+        buf.add_u8(TsonType::STR.code())?;
         self.add_cstring(buf, value)
     }
 
@@ -318,3 +371,23 @@ impl Serializer {
         buf.add_u8(0)
     }
 }
+
+fn canonical_f64(value: f64) -> f64 {
+    if value.is_nan() {
+        f64::NAN
+    } else if value == 0.0 {
+        0.0
+    } else {
+        value
+    }
+}
+
+fn canonical_f32(value: f32) -> f32 {
+    if value.is_nan() {
+        f32::NAN
+    } else if value == 0.0 {
+        0.0
+    } else {
+        value
+    }
+}
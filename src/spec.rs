@@ -1,66 +1,201 @@
-pub trait TsonTypedListType {
-    fn to_int32() -> u8;
+use std::convert::TryFrom;
+use std::fmt;
+
+use ::{TsonError, TsonResult, Value};
+use deser::Reader;
+
+/// Type of an encoded object, variants are named after the matching `Value` variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TsonType {
+    NULL,
+    STR,
+    I32,
+    F64,
+    BOOL,
+    LST,
+    MAP,
+    LSTU8,
+    LSTI8,
+    LSTU16,
+    LSTI16,
+    LSTU32,
+    LSTI32,
+    LSTU64,
+    LSTI64,
+    LSTF32,
+    LSTF64,
+    LSTSTR,
 }
 
-impl TsonTypedListType for u8 {
-    fn to_int32() -> u8 {
-        LIST_UINT8_TYPE
+impl TsonType {
+    pub fn code(&self) -> u8 {
+        match *self {
+            TsonType::NULL => NULL_TYPE,
+            TsonType::STR => STRING_TYPE,
+            TsonType::I32 => INTEGER_TYPE,
+            TsonType::F64 => DOUBLE_TYPE,
+            TsonType::BOOL => BOOL_TYPE,
+            TsonType::LST => LIST_TYPE,
+            TsonType::MAP => MAP_TYPE,
+            TsonType::LSTU8 => LIST_UINT8_TYPE,
+            TsonType::LSTI8 => LIST_INT8_TYPE,
+            TsonType::LSTU16 => LIST_UINT16_TYPE,
+            TsonType::LSTI16 => LIST_INT16_TYPE,
+            TsonType::LSTU32 => LIST_UINT32_TYPE,
+            TsonType::LSTI32 => LIST_INT32_TYPE,
+            TsonType::LSTU64 => LIST_UINT64_TYPE,
+            TsonType::LSTI64 => LIST_INT64_TYPE,
+            TsonType::LSTF32 => LIST_FLOAT32_TYPE,
+            TsonType::LSTF64 => LIST_FLOAT64_TYPE,
+            TsonType::LSTSTR => LIST_STRING_TYPE,
+        }
     }
-}
 
-impl TsonTypedListType for i8 {
-    fn to_int32() -> u8 {
-        LIST_INT8_TYPE
+    pub fn name(&self) -> &'static str {
+        match *self {
+            TsonType::NULL => "NULL",
+            TsonType::STR => "STR",
+            TsonType::I32 => "I32",
+            TsonType::F64 => "F64",
+            TsonType::BOOL => "BOOL",
+            TsonType::LST => "LST",
+            TsonType::MAP => "MAP",
+            TsonType::LSTU8 => "LSTU8",
+            TsonType::LSTI8 => "LSTI8",
+            TsonType::LSTU16 => "LSTU16",
+            TsonType::LSTI16 => "LSTI16",
+            TsonType::LSTU32 => "LSTU32",
+            TsonType::LSTI32 => "LSTI32",
+            TsonType::LSTU64 => "LSTU64",
+            TsonType::LSTI64 => "LSTI64",
+            TsonType::LSTF32 => "LSTF32",
+            TsonType::LSTF64 => "LSTF64",
+            TsonType::LSTSTR => "LSTSTR",
+        }
     }
-}
 
-impl TsonTypedListType for u16 {
-    fn to_int32() -> u8 {
-        LIST_UINT16_TYPE
+    /// Size in bytes of an element of a typed list, None for any other type.
+    pub fn element_size(&self) -> Option<usize> {
+        match *self {
+            TsonType::LSTU8 | TsonType::LSTI8 => Some(1),
+            TsonType::LSTU16 | TsonType::LSTI16 => Some(2),
+            TsonType::LSTU32 | TsonType::LSTI32 | TsonType::LSTF32 => Some(4),
+            TsonType::LSTU64 | TsonType::LSTI64 | TsonType::LSTF64 => Some(8),
+            _ => None,
+        }
     }
-}
 
-impl TsonTypedListType for i16 {
-    fn to_int32() -> u8 {
-        LIST_INT16_TYPE
+    /// True for the numeric typed lists, LSTSTR is not one as its elements have no fixed size.
+    pub fn is_typed_list(&self) -> bool {
+        self.element_size().is_some()
     }
 }
 
-impl TsonTypedListType for u32 {
-    fn to_int32() -> u8 {
-        LIST_UINT32_TYPE
+impl TryFrom<u8> for TsonType {
+    type Error = TsonError;
+
+    fn try_from(code: u8) -> TsonResult<Self> {
+        match code {
+            NULL_TYPE => Ok(TsonType::NULL),
+            STRING_TYPE => Ok(TsonType::STR),
+            INTEGER_TYPE => Ok(TsonType::I32),
+            DOUBLE_TYPE => Ok(TsonType::F64),
+            BOOL_TYPE => Ok(TsonType::BOOL),
+            LIST_TYPE => Ok(TsonType::LST),
+            MAP_TYPE => Ok(TsonType::MAP),
+            LIST_UINT8_TYPE => Ok(TsonType::LSTU8),
+            LIST_INT8_TYPE => Ok(TsonType::LSTI8),
+            LIST_UINT16_TYPE => Ok(TsonType::LSTU16),
+            LIST_INT16_TYPE => Ok(TsonType::LSTI16),
+            LIST_UINT32_TYPE => Ok(TsonType::LSTU32),
+            LIST_INT32_TYPE => Ok(TsonType::LSTI32),
+            LIST_UINT64_TYPE => Ok(TsonType::LSTU64),
+            LIST_INT64_TYPE => Ok(TsonType::LSTI64),
+            LIST_FLOAT32_TYPE => Ok(TsonType::LSTF32),
+            LIST_FLOAT64_TYPE => Ok(TsonType::LSTF64),
+            LIST_STRING_TYPE => Ok(TsonType::LSTSTR),
+            _ => Err(TsonError::new(format!("wrong format -- unknown type {}", code))),
+        }
     }
 }
 
-impl TsonTypedListType for i32 {
-    fn to_int32() -> u8 {
-        LIST_INT32_TYPE
+impl fmt::Display for TsonType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
     }
 }
 
-impl TsonTypedListType for u64 {
-    fn to_int32() -> u8 {
-        LIST_UINT64_TYPE
-    }
+mod private {
+    pub trait Sealed {}
 }
 
-impl TsonTypedListType for i64 {
+/// Rust element type of a typed list, lets generic code handle the ten typed lists at once.
+/// Sealed, only the ten element types implement it.
+pub trait TsonTypedListType: private::Sealed + Sized + Copy + Default {
     fn to_int32() -> u8 {
-        LIST_INT64_TYPE
+        Self::tson_type().code()
     }
+    fn tson_type() -> TsonType;
+    fn into_value(vec: Vec<Self>) -> Value;
+    /// Gives the value back when it is not a list of Self.
+    fn try_from_value(value: Value) -> Result<Vec<Self>, Value>;
+    fn as_slice(value: &Value) -> Option<&[Self]>;
+    fn as_vec_mut(value: &mut Value) -> Option<&mut Vec<Self>>;
+    /// Reads `dest.len()` little endian elements.
+    fn read_into(reader: &mut dyn Reader, dest: &mut [Self]) -> TsonResult<()>;
 }
 
-impl TsonTypedListType for f32 {
-    fn to_int32() -> u8 {
-        LIST_FLOAT32_TYPE
-    }
+macro_rules! typed_list_type {
+    ($t:ty, $variant:ident, $read_into:ident) => {
+        impl private::Sealed for $t {}
+
+        impl TsonTypedListType for $t {
+            fn tson_type() -> TsonType {
+                TsonType::$variant
+            }
+
+            fn into_value(vec: Vec<Self>) -> Value {
+                Value::$variant(vec)
+            }
+
+            fn try_from_value(value: Value) -> Result<Vec<Self>, Value> {
+                match value {
+                    Value::$variant(vec) => Ok(vec),
+                    _ => Err(value),
+                }
+            }
+
+            fn as_slice(value: &Value) -> Option<&[Self]> {
+                match *value {
+                    Value::$variant(ref vec) => Some(vec),
+                    _ => None,
+                }
+            }
+
+            fn as_vec_mut(value: &mut Value) -> Option<&mut Vec<Self>> {
+                match *value {
+                    Value::$variant(ref mut vec) => Some(vec),
+                    _ => None,
+                }
+            }
+
+            fn read_into(reader: &mut dyn Reader, dest: &mut [Self]) -> TsonResult<()> {
+                reader.$read_into(dest)
+            }
+        }
+    };
 }
 
-impl TsonTypedListType for f64 {
-    fn to_int32() -> u8 {
-        LIST_FLOAT64_TYPE
-    }
-}
+typed_list_type!(u8, LSTU8, read_u8_into);
+typed_list_type!(i8, LSTI8, read_i8_into);
+typed_list_type!(u16, LSTU16, read_u16_into);
+typed_list_type!(i16, LSTI16, read_i16_into);
+typed_list_type!(u32, LSTU32, read_u32_into);
+typed_list_type!(i32, LSTI32, read_i32_into);
+typed_list_type!(u64, LSTU64, read_u64_into);
+typed_list_type!(i64, LSTI64, read_i64_into);
+typed_list_type!(f32, LSTF32, read_f32_into);
+typed_list_type!(f64, LSTF64, read_f64_into);
 
 pub const NULL_TYPE: u8 = 0;
 pub const STRING_TYPE: u8 = 1;
@@ -87,4 +222,27 @@ pub const LIST_FLOAT64_TYPE: u8 = 111;
 
 pub const LIST_STRING_TYPE: u8 = 112;
 
-pub const MAX_LIST_LENGTH: usize = std::u32::MAX as usize;
\ No newline at end of file
+pub const MAX_LIST_LENGTH: usize = u32::MAX as usize;
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use spec::TsonType;
+    use {encode, TsonResult, VERSION};
+
+    #[test]
+    fn tson_type() -> TsonResult<()> {
+        let values = tson!([null, "a", 1, 1.0, true, [], {}, u8[], i8[], u16[], i16[], u32[], i32[], u64[], i64[], f32[], f64[], str[]]);
+        for value in values.to_list()?.iter() {
+            let t = value.tson_type();
+            let bytes = encode(value)?;
+            assert_eq!(bytes[1 + VERSION.len() + 1], t.code());
+            assert_eq!(TsonType::try_from(t.code())?, t);
+            assert_eq!(t.name(), format!("{:?}", t));
+            assert_eq!(t.is_typed_list(), t.element_size().is_some());
+        }
+        assert_eq!(TsonType::LSTF32.element_size(), Some(4));
+        assert!(!TsonType::LSTSTR.is_typed_list());
+        assert!(TsonType::try_from(42).is_err());
+        Ok(())
+    }
+}
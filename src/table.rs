@@ -0,0 +1,206 @@
+use std::convert::TryFrom;
+
+use ::{Map, StrVec, TsonError, TsonResult, Value};
+use spec::TsonTypedListType;
+
+/// Columns of equal length, typed lists or LSTSTR, in order.
+///
+/// On the wire a table is a MAP of column name to column, columns being sorted by name
+/// when read from a map not preserving order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Table {
+    columns: Vec<(String, Value)>,
+    rows: usize,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Table::default()
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.rows
+    }
+
+    pub fn column_count(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn column_names(&self) -> impl Iterator<Item = &str> {
+        self.columns.iter().map(|c| c.0.as_str())
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.columns.iter().map(|c| (c.0.as_str(), &c.1))
+    }
+
+    /// Appends a column, of the length of the others.
+    pub fn add_column<S>(&mut self, name: S, column: Value) -> TsonResult<()> where S: Into<String> {
+        let name = name.into();
+        if self.column(&name).is_some() {
+            return Err(TsonError::new(format!("column \"{}\" -- already exists", name)));
+        }
+        let len = column_len(&column)
+            .ok_or_else(|| TsonError::new(format!("column \"{}\" -- typed list expected -- found {}", name, column.tson_type())))?;
+        if self.columns.is_empty() {
+            self.rows = len;
+        } else if len != self.rows {
+            return Err(TsonError::new(format!("column \"{}\" -- length {} expected -- found {}", name, self.rows, len)));
+        }
+        self.columns.push((name, column));
+        Ok(())
+    }
+
+    pub fn column(&self, name: &str) -> Option<&Value> {
+        self.columns.iter().find(|c| c.0 == name).map(|c| &c.1)
+    }
+
+    /// Elements of a numeric column, `T` being its element type.
+    pub fn typed_column<T>(&self, name: &str) -> TsonResult<&[T]> where T: TsonTypedListType {
+        let column = self.get(name)?;
+        T::as_slice(column).ok_or_else(|| column_error(name, column.type_error(T::tson_type())))
+    }
+
+    pub fn str_column(&self, name: &str) -> TsonResult<&StrVec> {
+        let column = self.get(name)?;
+        column.as_strvec().map_err(|e| column_error(name, e))
+    }
+
+    /// Table of the `names` columns, in that order.
+    pub fn select(&self, names: &[&str]) -> TsonResult<Table> {
+        let mut table = Table::new();
+        for name in names {
+            table.add_column(*name, self.get(name)?.clone())?;
+        }
+        Ok(table)
+    }
+
+    /// Table of the `indices` rows, in that order, rows being repeatable.
+    pub fn take(&self, indices: &[usize]) -> TsonResult<Table> {
+        if let Some(index) = indices.iter().find(|i| **i >= self.rows) {
+            return Err(TsonError::new(format!("row {} -- out of range -- {} rows", index, self.rows)));
+        }
+        let columns = self.columns.iter()
+            .map(|(name, column)| take(column, indices).map(|column| (name.clone(), column)))
+            .collect::<TsonResult<Vec<_>>>()?;
+        Ok(Table { columns, rows: indices.len() })
+    }
+
+    /// Names and columns, in order.
+    pub fn into_columns(self) -> Vec<(String, Value)> {
+        self.columns
+    }
+
+    fn get(&self, name: &str) -> TsonResult<&Value> {
+        self.column(name).ok_or_else(|| TsonError::new(format!("column \"{}\" -- not found", name)))
+    }
+}
+
+fn column_error(name: &str, error: TsonError) -> TsonError {
+    TsonError::new(format!("column \"{}\" -- {}", name, error))
+}
+
+pub(crate) fn column_len(column: &Value) -> Option<usize> {
+    match *column {
+        Value::LSTU8(ref v) => Some(v.len()),
+        Value::LSTI8(ref v) => Some(v.len()),
+        Value::LSTU16(ref v) => Some(v.len()),
+        Value::LSTI16(ref v) => Some(v.len()),
+        Value::LSTU32(ref v) => Some(v.len()),
+        Value::LSTI32(ref v) => Some(v.len()),
+        Value::LSTU64(ref v) => Some(v.len()),
+        Value::LSTI64(ref v) => Some(v.len()),
+        Value::LSTF32(ref v) => Some(v.len()),
+        Value::LSTF64(ref v) => Some(v.len()),
+        Value::LSTSTR(ref v) => Some(v.bytes.iter().filter(|b| **b == 0).count()),
+        _ => None,
+    }
+}
+
+fn take(column: &Value, indices: &[usize]) -> TsonResult<Value> {
+    fn take_typed<T>(v: &[T], indices: &[usize]) -> Value where T: TsonTypedListType {
+        T::into_value(indices.iter().map(|i| v[*i]).collect())
+    }
+    Ok(match *column {
+        Value::LSTU8(ref v) => take_typed(v, indices),
+        Value::LSTI8(ref v) => take_typed(v, indices),
+        Value::LSTU16(ref v) => take_typed(v, indices),
+        Value::LSTI16(ref v) => take_typed(v, indices),
+        Value::LSTU32(ref v) => take_typed(v, indices),
+        Value::LSTI32(ref v) => take_typed(v, indices),
+        Value::LSTU64(ref v) => take_typed(v, indices),
+        Value::LSTI64(ref v) => take_typed(v, indices),
+        Value::LSTF32(ref v) => take_typed(v, indices),
+        Value::LSTF64(ref v) => take_typed(v, indices),
+        Value::LSTSTR(ref v) => {
+            let starts = v.build_starts()?;
+            let mut bytes = Vec::new();
+            for i in indices {
+                bytes.extend_from_slice(&v.bytes[starts[*i]..starts[*i + 1]]);
+            }
+            Value::LSTSTR(StrVec::from_bytes_unchecked(bytes))
+        }
+        _ => column.clone(),
+    })
+}
+
+impl TryFrom<Value> for Table {
+    type Error = TsonError;
+
+    fn try_from(value: Value) -> TsonResult<Self> {
+        let map = Map::<String, Value>::try_from(value)?;
+        let mut columns: Vec<(String, Value)> = map.into_iter().collect();
+        if cfg!(not(feature = "preserve_order")) {
+            columns.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        }
+        let mut table = Table::new();
+        for (name, column) in columns {
+            table.add_column(name, column)?;
+        }
+        Ok(table)
+    }
+}
+
+impl From<Table> for Value {
+    fn from(table: Table) -> Self {
+        Value::MAP(table.columns.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use table::Table;
+    use Value;
+
+    #[test]
+    fn table() {
+        let value = tson!({"a": f64[1.0, 2.0, 3.0], "b": str["x", "y", "z"], "c": i32[1, 2, 3]});
+        let table = Table::try_from(value.clone()).unwrap();
+        assert_eq!(table.row_count(), 3);
+        assert_eq!(table.column_names().collect::<Vec<_>>(), vec!["a", "b", "c"]);
+        assert_eq!(table.typed_column::<f64>("a").unwrap(), &[1.0, 2.0, 3.0]);
+        assert_eq!(table.typed_column::<f64>("c").unwrap_err().to_string(), "column \"c\" -- LSTF64 expected -- found LSTI32");
+        assert_eq!(table.str_column("b").unwrap().try_to_vec().unwrap(), vec!["x", "y", "z"]);
+        assert_eq!(table.str_column("d").unwrap_err().to_string(), "column \"d\" -- not found");
+        assert_eq!(Value::from(table.clone()), value);
+        assert_eq!(table.clone().into_columns()[2], ("c".to_owned(), tson!(i32[1, 2, 3])));
+
+        let taken = table.select(&["c", "b"]).unwrap().take(&[2, 0, 2]).unwrap();
+        assert_eq!(taken.column_names().collect::<Vec<_>>(), vec!["c", "b"]);
+        assert_eq!(Value::from(taken), tson!({"c": i32[3, 1, 3], "b": str["z", "x", "z"]}));
+        assert_eq!(table.take(&[3]).unwrap_err().to_string(), "row 3 -- out of range -- 3 rows");
+    }
+
+    #[test]
+    fn invalid_tables() {
+        assert_eq!(Table::try_from(tson!({"a": f64[1.0], "b": i32[1, 2]})).unwrap_err().to_string(),
+                   "column \"b\" -- length 1 expected -- found 2");
+        assert_eq!(Table::try_from(tson!({"a": [1]})).unwrap_err().to_string(), "column \"a\" -- typed list expected -- found LST");
+        assert_eq!(Table::try_from(tson!([1])).unwrap_err().to_string(), "MAP expected -- found LST");
+        let mut table = Table::new();
+        table.add_column("a", tson!(u8[1])).unwrap();
+        assert!(table.add_column("a", tson!(u8[2])).is_err());
+    }
+}
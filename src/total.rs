@@ -0,0 +1,187 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use ::{Map, Value};
+
+/// A `Value` with a total order, usable as a `HashMap`/`HashSet`/`BTreeMap` key or sorted.
+///
+/// Floats are compared with IEEE totalOrder (`f64::total_cmp`), so NaN equals itself and
+/// -0.0 is lower than 0.0. Values of different types are ordered by `TsonType`, maps are
+/// compared as their entries sorted by key and hashed independently of their order.
+#[derive(Debug, Clone)]
+pub struct TotalValue(pub Value);
+
+impl TotalValue {
+    pub fn into_inner(self) -> Value {
+        self.0
+    }
+}
+
+impl From<Value> for TotalValue {
+    fn from(value: Value) -> Self {
+        TotalValue(value)
+    }
+}
+
+impl PartialEq for TotalValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.total_cmp(&other.0) == Ordering::Equal
+    }
+}
+
+impl Eq for TotalValue {}
+
+impl PartialOrd for TotalValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TotalValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for TotalValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.total_hash(state)
+    }
+}
+
+impl Value {
+    /// Total order used by `TotalValue`.
+    pub fn total_cmp(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::NULL, Value::NULL) => Ordering::Equal,
+            (Value::STR(a), Value::STR(b)) => a.cmp(b),
+            (Value::I32(a), Value::I32(b)) => a.cmp(b),
+            (Value::F64(a), Value::F64(b)) => a.total_cmp(b),
+            (Value::BOOL(a), Value::BOOL(b)) => a.cmp(b),
+            (Value::LST(a), Value::LST(b)) => cmp_slices(a, b, |a, b| a.total_cmp(b)),
+            (Value::MAP(a), Value::MAP(b)) => cmp_maps(a, b),
+            (Value::LSTU8(a), Value::LSTU8(b)) => a.cmp(b),
+            (Value::LSTI8(a), Value::LSTI8(b)) => a.cmp(b),
+            (Value::LSTU16(a), Value::LSTU16(b)) => a.cmp(b),
+            (Value::LSTI16(a), Value::LSTI16(b)) => a.cmp(b),
+            (Value::LSTU32(a), Value::LSTU32(b)) => a.cmp(b),
+            (Value::LSTI32(a), Value::LSTI32(b)) => a.cmp(b),
+            (Value::LSTU64(a), Value::LSTU64(b)) => a.cmp(b),
+            (Value::LSTI64(a), Value::LSTI64(b)) => a.cmp(b),
+            (Value::LSTF32(a), Value::LSTF32(b)) => cmp_slices(a, b, |a, b| a.total_cmp(b)),
+            (Value::LSTF64(a), Value::LSTF64(b)) => cmp_slices(a, b, |a, b| a.total_cmp(b)),
+            // The terminator being the lowest byte, this is the order of the string sequences.
+            (Value::LSTSTR(a), Value::LSTSTR(b)) => a.bytes.cmp(&b.bytes),
+            _ => self.tson_type().cmp(&other.tson_type()),
+        }
+    }
+
+    /// Hash consistent with `total_cmp`.
+    pub fn total_hash<H: Hasher>(&self, state: &mut H) {
+        self.tson_type().hash(state);
+        match *self {
+            Value::NULL => {}
+            Value::STR(ref v) => v.hash(state),
+            Value::I32(v) => v.hash(state),
+            Value::F64(v) => v.to_bits().hash(state),
+            Value::BOOL(v) => v.hash(state),
+            Value::LST(ref v) => {
+                v.len().hash(state);
+                v.iter().for_each(|e| e.total_hash(state));
+            }
+            Value::MAP(ref v) => {
+                // Entries are hashed on their own and summed, which does not depend on their order.
+                let sum = v.iter().fold(0u64, |sum, (k, v)| {
+                    let mut hasher = DefaultHasher::new();
+                    k.hash(&mut hasher);
+                    v.total_hash(&mut hasher);
+                    sum.wrapping_add(hasher.finish())
+                });
+                v.len().hash(state);
+                sum.hash(state);
+            }
+            Value::LSTU8(ref v) => v.hash(state),
+            Value::LSTI8(ref v) => v.hash(state),
+            Value::LSTU16(ref v) => v.hash(state),
+            Value::LSTI16(ref v) => v.hash(state),
+            Value::LSTU32(ref v) => v.hash(state),
+            Value::LSTI32(ref v) => v.hash(state),
+            Value::LSTU64(ref v) => v.hash(state),
+            Value::LSTI64(ref v) => v.hash(state),
+            Value::LSTF32(ref v) => {
+                v.len().hash(state);
+                v.iter().for_each(|e| e.to_bits().hash(state));
+            }
+            Value::LSTF64(ref v) => {
+                v.len().hash(state);
+                v.iter().for_each(|e| e.to_bits().hash(state));
+            }
+            Value::LSTSTR(ref v) => v.bytes.hash(state),
+        }
+    }
+}
+
+fn cmp_slices<T, F>(a: &[T], b: &[T], cmp: F) -> Ordering where F: Fn(&T, &T) -> Ordering {
+    for (a, b) in a.iter().zip(b.iter()) {
+        match cmp(a, b) {
+            Ordering::Equal => {}
+            ordering => return ordering,
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+fn cmp_maps(a: &Map<String, Value>, b: &Map<String, Value>) -> Ordering {
+    let mut a: Vec<(&String, &Value)> = a.iter().collect();
+    let mut b: Vec<(&String, &Value)> = b.iter().collect();
+    a.sort_unstable_by(|x, y| x.0.cmp(y.0));
+    b.sort_unstable_by(|x, y| x.0.cmp(y.0));
+    cmp_slices(&a, &b, |x, y| x.0.cmp(y.0).then_with(|| x.1.total_cmp(y.1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use total::TotalValue;
+    use {Map, Value};
+
+    #[test]
+    fn total_order() {
+        let mut values: Vec<TotalValue> = vec![
+            tson!("b"), tson!(f64[1.0, 0.0]), tson!(2.0), Value::F64(f64::NAN), tson!(null), tson!("a"),
+            tson!(-0.0), tson!(0.0), tson!(1), tson!(f64[1.0]),
+        ].into_iter().map(TotalValue).collect();
+        values.sort();
+        let sorted: Vec<String> = values.into_iter().map(|v| v.0.to_string()).collect();
+        assert_eq!(sorted, vec!["null", "\"a\"", "\"b\"", "1", "-0.0", "0.0", "2.0", "NaN", "f64[1.0]", "f64[1.0, 0.0]"]);
+
+        assert_eq!(TotalValue(Value::F64(f64::NAN)), TotalValue(Value::F64(f64::NAN)));
+        assert_ne!(TotalValue(tson!(0.0)), TotalValue(tson!(-0.0)));
+        assert!(TotalValue(tson!(str["a"])) < TotalValue(tson!(str["a", "b"])));
+        assert!(TotalValue(tson!(str["a"])) < TotalValue(tson!(str["ab"])));
+    }
+
+    #[test]
+    fn hash_maps() {
+        let mut a = Map::new();
+        let mut b = Map::new();
+        for i in 0..32 {
+            a.insert(format!("k{}", i), Value::I32(i));
+            b.insert(format!("k{}", 31 - i), Value::I32(31 - i));
+        }
+
+        let mut set = HashSet::new();
+        set.insert(TotalValue(Value::MAP(a)));
+        set.insert(TotalValue(Value::MAP(b)));
+        set.insert(TotalValue(tson!([f64[f64::NAN]])));
+        set.insert(TotalValue(tson!([f64[f64::NAN]])));
+        assert_eq!(set.len(), 2);
+
+        let mut groups: HashMap<TotalValue, usize> = HashMap::new();
+        for v in [tson!({"a": 1, "b": 2}), tson!({"b": 2, "a": 1}), tson!({"a": 2})] {
+            *groups.entry(TotalValue(v)).or_insert(0) += 1;
+        }
+        assert_eq!(groups[&TotalValue(tson!({"a": 1, "b": 2}))], 2);
+    }
+}
@@ -0,0 +1,256 @@
+use ::{Map, Path, PathSegment, StrVec, Value};
+
+/// Tells the walker how to go on after a hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Walk {
+    Continue,
+    /// Does not visit the children of the value.
+    Skip,
+    /// Ends the walk.
+    Stop,
+}
+
+/// Like `Walk`, a value being able to be replaced in place, its replacement not being visited.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalkMut {
+    Continue,
+    Skip,
+    Stop,
+    Replace(Value),
+}
+
+/// Hooks called by `Value::walk`, depth first, map entries in map order.
+///
+/// `visit` dispatches to one hook per variant, all of them continuing by default.
+pub trait Visitor {
+    fn visit(&mut self, path: &Path, value: &Value) -> Walk {
+        match *value {
+            Value::NULL => self.visit_null(path),
+            Value::STR(ref v) => self.visit_str(path, v),
+            Value::I32(v) => self.visit_i32(path, v),
+            Value::F64(v) => self.visit_f64(path, v),
+            Value::BOOL(v) => self.visit_bool(path, v),
+            Value::LST(ref v) => self.visit_lst(path, v),
+            Value::MAP(ref v) => self.visit_map(path, v),
+            Value::LSTU8(ref v) => self.visit_lstu8(path, v),
+            Value::LSTI8(ref v) => self.visit_lsti8(path, v),
+            Value::LSTU16(ref v) => self.visit_lstu16(path, v),
+            Value::LSTI16(ref v) => self.visit_lsti16(path, v),
+            Value::LSTU32(ref v) => self.visit_lstu32(path, v),
+            Value::LSTI32(ref v) => self.visit_lsti32(path, v),
+            Value::LSTU64(ref v) => self.visit_lstu64(path, v),
+            Value::LSTI64(ref v) => self.visit_lsti64(path, v),
+            Value::LSTF32(ref v) => self.visit_lstf32(path, v),
+            Value::LSTF64(ref v) => self.visit_lstf64(path, v),
+            Value::LSTSTR(ref v) => self.visit_lststr(path, v),
+        }
+    }
+
+    fn visit_null(&mut self, _path: &Path) -> Walk { Walk::Continue }
+    fn visit_str(&mut self, _path: &Path, _value: &str) -> Walk { Walk::Continue }
+    fn visit_i32(&mut self, _path: &Path, _value: i32) -> Walk { Walk::Continue }
+    fn visit_f64(&mut self, _path: &Path, _value: f64) -> Walk { Walk::Continue }
+    fn visit_bool(&mut self, _path: &Path, _value: bool) -> Walk { Walk::Continue }
+    /// Called before the elements.
+    fn visit_lst(&mut self, _path: &Path, _value: &[Value]) -> Walk { Walk::Continue }
+    /// Called before the values.
+    fn visit_map(&mut self, _path: &Path, _value: &Map<String, Value>) -> Walk { Walk::Continue }
+    fn visit_lstu8(&mut self, _path: &Path, _value: &[u8]) -> Walk { Walk::Continue }
+    fn visit_lsti8(&mut self, _path: &Path, _value: &[i8]) -> Walk { Walk::Continue }
+    fn visit_lstu16(&mut self, _path: &Path, _value: &[u16]) -> Walk { Walk::Continue }
+    fn visit_lsti16(&mut self, _path: &Path, _value: &[i16]) -> Walk { Walk::Continue }
+    fn visit_lstu32(&mut self, _path: &Path, _value: &[u32]) -> Walk { Walk::Continue }
+    fn visit_lsti32(&mut self, _path: &Path, _value: &[i32]) -> Walk { Walk::Continue }
+    fn visit_lstu64(&mut self, _path: &Path, _value: &[u64]) -> Walk { Walk::Continue }
+    fn visit_lsti64(&mut self, _path: &Path, _value: &[i64]) -> Walk { Walk::Continue }
+    fn visit_lstf32(&mut self, _path: &Path, _value: &[f32]) -> Walk { Walk::Continue }
+    fn visit_lstf64(&mut self, _path: &Path, _value: &[f64]) -> Walk { Walk::Continue }
+    fn visit_lststr(&mut self, _path: &Path, _value: &StrVec) -> Walk { Walk::Continue }
+}
+
+/// Hooks called by `Value::walk_mut`, able to modify or replace the values.
+pub trait VisitorMut {
+    fn visit(&mut self, path: &Path, value: &mut Value) -> WalkMut {
+        match *value {
+            Value::NULL => self.visit_null(path),
+            Value::STR(ref mut v) => self.visit_str(path, v),
+            Value::I32(ref mut v) => self.visit_i32(path, v),
+            Value::F64(ref mut v) => self.visit_f64(path, v),
+            Value::BOOL(ref mut v) => self.visit_bool(path, v),
+            Value::LST(ref mut v) => self.visit_lst(path, v),
+            Value::MAP(ref mut v) => self.visit_map(path, v),
+            Value::LSTU8(ref mut v) => self.visit_lstu8(path, v),
+            Value::LSTI8(ref mut v) => self.visit_lsti8(path, v),
+            Value::LSTU16(ref mut v) => self.visit_lstu16(path, v),
+            Value::LSTI16(ref mut v) => self.visit_lsti16(path, v),
+            Value::LSTU32(ref mut v) => self.visit_lstu32(path, v),
+            Value::LSTI32(ref mut v) => self.visit_lsti32(path, v),
+            Value::LSTU64(ref mut v) => self.visit_lstu64(path, v),
+            Value::LSTI64(ref mut v) => self.visit_lsti64(path, v),
+            Value::LSTF32(ref mut v) => self.visit_lstf32(path, v),
+            Value::LSTF64(ref mut v) => self.visit_lstf64(path, v),
+            Value::LSTSTR(ref mut v) => self.visit_lststr(path, v),
+        }
+    }
+
+    fn visit_null(&mut self, _path: &Path) -> WalkMut { WalkMut::Continue }
+    fn visit_str(&mut self, _path: &Path, _value: &mut String) -> WalkMut { WalkMut::Continue }
+    fn visit_i32(&mut self, _path: &Path, _value: &mut i32) -> WalkMut { WalkMut::Continue }
+    fn visit_f64(&mut self, _path: &Path, _value: &mut f64) -> WalkMut { WalkMut::Continue }
+    fn visit_bool(&mut self, _path: &Path, _value: &mut bool) -> WalkMut { WalkMut::Continue }
+    /// Called before the elements, which are the ones of the list once modified.
+    fn visit_lst(&mut self, _path: &Path, _value: &mut Vec<Value>) -> WalkMut { WalkMut::Continue }
+    /// Called before the values, which are the ones of the map once modified.
+    fn visit_map(&mut self, _path: &Path, _value: &mut Map<String, Value>) -> WalkMut { WalkMut::Continue }
+    fn visit_lstu8(&mut self, _path: &Path, _value: &mut Vec<u8>) -> WalkMut { WalkMut::Continue }
+    fn visit_lsti8(&mut self, _path: &Path, _value: &mut Vec<i8>) -> WalkMut { WalkMut::Continue }
+    fn visit_lstu16(&mut self, _path: &Path, _value: &mut Vec<u16>) -> WalkMut { WalkMut::Continue }
+    fn visit_lsti16(&mut self, _path: &Path, _value: &mut Vec<i16>) -> WalkMut { WalkMut::Continue }
+    fn visit_lstu32(&mut self, _path: &Path, _value: &mut Vec<u32>) -> WalkMut { WalkMut::Continue }
+    fn visit_lsti32(&mut self, _path: &Path, _value: &mut Vec<i32>) -> WalkMut { WalkMut::Continue }
+    fn visit_lstu64(&mut self, _path: &Path, _value: &mut Vec<u64>) -> WalkMut { WalkMut::Continue }
+    fn visit_lsti64(&mut self, _path: &Path, _value: &mut Vec<i64>) -> WalkMut { WalkMut::Continue }
+    fn visit_lstf32(&mut self, _path: &Path, _value: &mut Vec<f32>) -> WalkMut { WalkMut::Continue }
+    fn visit_lstf64(&mut self, _path: &Path, _value: &mut Vec<f64>) -> WalkMut { WalkMut::Continue }
+    fn visit_lststr(&mut self, _path: &Path, _value: &mut StrVec) -> WalkMut { WalkMut::Continue }
+}
+
+impl Value {
+    /// Visits self and its descendants, with an explicit stack so that any depth can be walked.
+    pub fn walk<V>(&self, visitor: &mut V) where V: Visitor {
+        let mut path = Path::new();
+        // Depth of the parent path, segment and value of the values left to visit.
+        let mut stack: Vec<(usize, Option<PathSegment>, &Value)> = vec![(0, None, self)];
+        while let Some((depth, segment, value)) = stack.pop() {
+            path.truncate(depth);
+            path.extend(segment);
+            match visitor.visit(&path, value) {
+                Walk::Continue => {}
+                Walk::Skip => continue,
+                Walk::Stop => return,
+            }
+            let start = stack.len();
+            match *value {
+                Value::MAP(ref map) => {
+                    stack.extend(map.iter().map(|(k, v)| (path.len(), Some(PathSegment::Key(k.clone())), v)));
+                }
+                Value::LST(ref list) => {
+                    stack.extend(list.iter().enumerate().map(|(i, v)| (path.len(), Some(PathSegment::Index(i)), v)));
+                }
+                _ => {}
+            }
+            stack[start..].reverse();
+        }
+    }
+
+    pub fn walk_mut<V>(&mut self, visitor: &mut V) where V: VisitorMut {
+        let mut path = Path::new();
+        let mut stack: Vec<(usize, Option<PathSegment>, &mut Value)> = vec![(0, None, self)];
+        while let Some((depth, segment, value)) = stack.pop() {
+            path.truncate(depth);
+            path.extend(segment);
+            match visitor.visit(&path, value) {
+                WalkMut::Continue => {}
+                WalkMut::Skip => continue,
+                WalkMut::Stop => return,
+                WalkMut::Replace(replacement) => {
+                    *value = replacement;
+                    continue;
+                }
+            }
+            let start = stack.len();
+            match *value {
+                Value::MAP(ref mut map) => {
+                    stack.extend(map.iter_mut().map(|(k, v)| (path.len(), Some(PathSegment::Key(k.clone())), v)));
+                }
+                Value::LST(ref mut list) => {
+                    stack.extend(list.iter_mut().enumerate().map(|(i, v)| (path.len(), Some(PathSegment::Index(i)), v)));
+                }
+                _ => {}
+            }
+            stack[start..].reverse();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use visit::{Visitor, VisitorMut, Walk, WalkMut};
+    use {Path, Value};
+
+    #[derive(Default)]
+    struct Stats {
+        paths: Vec<String>,
+        floats: usize,
+    }
+
+    impl Visitor for Stats {
+        fn visit(&mut self, path: &Path, value: &Value) -> Walk {
+            self.paths.push(path.to_string());
+            match *value {
+                Value::LSTF64(ref v) => self.visit_lstf64(path, v),
+                Value::STR(ref v) if v == "stop" => Walk::Stop,
+                Value::MAP(_) if path.to_string() == "/0/skipped" => Walk::Skip,
+                _ => Walk::Continue,
+            }
+        }
+
+        fn visit_lstf64(&mut self, _path: &Path, value: &[f64]) -> Walk {
+            self.floats += value.len();
+            Walk::Continue
+        }
+    }
+
+    #[test]
+    fn walk() {
+        let value = tson!([{"skipped": {"a": f64[1.0]}}, [f64[1.0, 2.0], "stop", f64[3.0]]]);
+        let mut stats = Stats::default();
+        value.walk(&mut stats);
+        assert_eq!(stats.paths, vec!["", "/0", "/0/skipped", "/1", "/1/0", "/1/1"]);
+        assert_eq!(stats.floats, 2);
+    }
+
+    struct Redact;
+
+    impl VisitorMut for Redact {
+        fn visit(&mut self, path: &Path, value: &mut Value) -> WalkMut {
+            match *value {
+                Value::LSTI32(ref v) => WalkMut::Replace(Value::LSTF64(v.iter().map(|e| f64::from(*e)).collect())),
+                _ if path.to_string().ends_with("/password") => WalkMut::Replace(Value::NULL),
+                Value::STR(ref mut v) => self.visit_str(path, v),
+                _ => WalkMut::Continue,
+            }
+        }
+
+        fn visit_str(&mut self, _path: &Path, value: &mut String) -> WalkMut {
+            *value = value.to_uppercase();
+            WalkMut::Continue
+        }
+    }
+
+    #[test]
+    fn walk_mut() {
+        let mut value = tson!({"users": [{"name": "a", "password": "x"}], "ids": i32[1, 2]});
+        value.walk_mut(&mut Redact);
+        assert_eq!(value, tson!({"users": [{"name": "A", "password": null}], "ids": f64[1.0, 2.0]}));
+
+        let mut depth = 0;
+        let mut value = Value::NULL;
+        for _ in 0..100_000 {
+            value = Value::LST(vec![value]);
+        }
+        struct Depth<'a>(&'a mut usize);
+        impl<'a> Visitor for Depth<'a> {
+            fn visit_lst(&mut self, path: &Path, _value: &[Value]) -> Walk {
+                *self.0 = path.len();
+                Walk::Continue
+            }
+        }
+        value.walk(&mut Depth(&mut depth));
+        assert_eq!(depth, 99_999);
+        // Dropping such a value recurses, it is taken apart first.
+        while let Value::LST(mut list) = value {
+            value = list.pop().unwrap_or(Value::NULL);
+        }
+    }
+}